@@ -0,0 +1,297 @@
+use sdl2::keyboard::Keycode;
+use tetris::engine::{parse_variant, Input};
+
+use std::collections::HashSet;
+
+/// Key opening the controls screen, pausing the game if it's running. It
+/// can't be bound to an action, and works even with no key left to pause,
+/// so that the controls can always be fixed from within the game.
+pub const CONTROLS_KEY: Keycode = Keycode::F3;
+
+/// What a key can do, in the game or around it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    SoftDrop,
+    HardDrop,
+    RotateCW,
+    RotateCCW,
+    Hold,
+    Pause,
+    /// Starts a new game, once over or while paused.
+    Restart,
+    /// Ends the game, or leaves once it is over.
+    End,
+    SaveQuit,
+    /// Plays a finished game again.
+    Review,
+}
+
+impl Action {
+    pub const ALL: [Action; 12] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::SoftDrop,
+        Action::HardDrop,
+        Action::RotateCW,
+        Action::RotateCCW,
+        Action::Hold,
+        Action::Pause,
+        Action::Restart,
+        Action::End,
+        Action::SaveQuit,
+        Action::Review,
+    ];
+
+    /// The game input the action stands for, if it is one.
+    pub fn input(self) -> Option<Input> {
+        match self {
+            Action::MoveLeft => Some(Input::MoveLeft),
+            Action::MoveRight => Some(Input::MoveRight),
+            Action::SoftDrop => Some(Input::SoftDrop),
+            Action::HardDrop => Some(Input::HardDrop),
+            Action::RotateCW => Some(Input::RotateCW),
+            Action::RotateCCW => Some(Input::RotateCCW),
+            Action::Hold => Some(Input::Hold),
+            _ => None,
+        }
+    }
+
+    fn default_keys(self) -> &'static [Keycode] {
+        match self {
+            Action::MoveLeft => &[Keycode::Left],
+            Action::MoveRight => &[Keycode::Right],
+            Action::SoftDrop => &[Keycode::Down],
+            Action::HardDrop => &[
+                Keycode::Space,
+                Keycode::LCtrl,
+                Keycode::RCtrl,
+                Keycode::PageDown,
+            ],
+            Action::RotateCW => &[Keycode::Up],
+            Action::RotateCCW => &[Keycode::Z],
+            Action::Hold => &[Keycode::C, Keycode::LShift, Keycode::RShift],
+            Action::Pause => &[Keycode::P],
+            Action::Restart => &[Keycode::F1],
+            Action::End => &[Keycode::Escape],
+            Action::SaveQuit => &[Keycode::F2],
+            Action::Review => &[Keycode::R],
+        }
+    }
+}
+
+/// Which action each key stands for. A key does one thing at most, an
+/// action can have several keys.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bindings {
+    /// In the order they were bound.
+    keys: Vec<(Keycode, Action)>,
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        let mut bindings = Bindings { keys: Vec::new() };
+        for &action in Action::ALL.iter() {
+            for &key in action.default_keys() {
+                bindings.bind(key, action);
+            }
+        }
+        bindings
+    }
+}
+
+impl Bindings {
+    pub fn action(&self, key: Keycode) -> Option<Action> {
+        self.keys
+            .iter()
+            .find(|(bound, _)| *bound == key)
+            .map(|(_, action)| *action)
+    }
+
+    pub fn keys(&self, action: Action) -> impl Iterator<Item = Keycode> + '_ {
+        self.keys
+            .iter()
+            .filter(move |(_, bound)| *bound == action)
+            .map(|(key, _)| *key)
+    }
+
+    /// Makes `key` do `action`, and nothing else it did before.
+    pub fn bind(&mut self, key: Keycode, action: Action) {
+        self.keys.retain(|(bound, _)| *bound != key);
+        self.keys.push((key, action));
+    }
+
+    pub fn clear(&mut self, action: Action) {
+        self.keys.retain(|(_, bound)| *bound != action);
+    }
+
+    /// Name of the first key doing `action`, to tell the player about it.
+    pub fn hint(&self, action: Action) -> String {
+        self.keys(action)
+            .next()
+            .map_or_else(|| "-".to_string(), Keycode::name)
+    }
+
+    /// Reads bindings as written by `to_text`: an action per line, then its
+    /// keys separated by commas. Actions left out keep their default keys.
+    pub fn parse(text: &str) -> Result<Bindings, String> {
+        let mut bindings = Bindings::default();
+        let mut actions = Vec::new();
+        let mut keys = HashSet::new();
+        for (nb, line) in text.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", nb + 1, message);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            let action = parse_variant(&Action::ALL, name)
+                .ok_or_else(|| error(format!("unknown action '{}'", name)))?;
+            if actions.contains(&action) {
+                return Err(error(format!("'{}' given twice", name)));
+            }
+            actions.push(action);
+            bindings.clear(action);
+            for key in value
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+            {
+                let keycode = Keycode::from_name(key)
+                    .ok_or_else(|| error(format!("unknown key '{}'", key)))?;
+                if keycode == CONTROLS_KEY {
+                    return Err(error(format!("'{}' opens the controls screen", key)));
+                }
+                if !keys.insert(keycode) {
+                    return Err(error(format!("'{}' bound twice", key)));
+                }
+                bindings.bind(keycode, action);
+            }
+        }
+        Ok(bindings)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = "# an action, then the keys doing it separated by commas\n".to_string();
+        for &action in Action::ALL.iter() {
+            let keys = self.keys(action).map(Keycode::name).collect::<Vec<_>>();
+            text.push_str(format!("{:?} {}", action, keys.join(", ")).trim_end());
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// The screen listing what every key does, where the keys can be changed.
+pub struct ControlsScreen {
+    pub bindings: Bindings,
+    /// Index in `Action::ALL`.
+    selected: usize,
+    /// Whether the next key pressed gets bound to the selected action.
+    capturing: bool,
+    pub changed: bool,
+}
+
+impl ControlsScreen {
+    pub fn new(bindings: Bindings) -> ControlsScreen {
+        ControlsScreen {
+            bindings,
+            selected: 0,
+            capturing: false,
+            changed: false,
+        }
+    }
+
+    /// Handles a key press, and tells whether the screen is still open.
+    pub fn press(&mut self, key: Keycode) -> bool {
+        let action = Action::ALL[self.selected];
+        if self.capturing {
+            self.capturing = false;
+            if key != Keycode::Escape && key != CONTROLS_KEY {
+                self.bindings.bind(key, action);
+                self.changed = true;
+            }
+            return true;
+        }
+        match key {
+            Keycode::Escape | CONTROLS_KEY => return false,
+            Keycode::Up => {
+                self.selected = (self.selected + Action::ALL.len() - 1) % Action::ALL.len()
+            }
+            Keycode::Down => self.selected = (self.selected + 1) % Action::ALL.len(),
+            Keycode::Return | Keycode::KpEnter => self.capturing = true,
+            Keycode::Backspace | Keycode::Delete => {
+                self.bindings.clear(action);
+                self.changed = true;
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// The lines to show, with whether each one is selected.
+    pub fn lines(&self) -> Vec<(String, bool)> {
+        let mut lines = vec![
+            ("Controls".to_string(), false),
+            ("Up/Down select, Enter add a key,".to_string(), false),
+            ("Backspace clear, Esc back".to_string(), false),
+        ];
+        for (index, &action) in Action::ALL.iter().enumerate() {
+            let keys = if self.capturing && index == self.selected {
+                "press a key...".to_string()
+            } else {
+                let keys = self.bindings.keys(action).map(Keycode::name);
+                keys.collect::<Vec<_>>().join(", ")
+            };
+            lines.push((format!("{:?}: {}", action, keys), index == self.selected));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_come_back_as_written() {
+        let mut bindings = Bindings::default();
+        bindings.bind(Keycode::X, Action::RotateCW);
+        bindings.bind(Keycode::A, Action::RotateCCW);
+        bindings.clear(Action::Review);
+        let parsed = Bindings::parse(&bindings.to_text()).unwrap();
+        for &action in Action::ALL.iter() {
+            assert!(
+                parsed.keys(action).eq(bindings.keys(action)),
+                "{:?}",
+                action
+            );
+        }
+    }
+
+    #[test]
+    fn left_out_actions_keep_their_keys() {
+        let bindings = Bindings::parse("# comment\n\nRotateCW X, Up\nHold\n").unwrap();
+        let keys = |action| bindings.keys(action).collect::<Vec<_>>();
+        assert_eq!(keys(Action::RotateCW), [Keycode::X, Keycode::Up]);
+        assert_eq!(keys(Action::Hold), []);
+        assert_eq!(keys(Action::RotateCCW), [Keycode::Z]);
+        assert_eq!(bindings.action(Keycode::C), None);
+        // a key taken from an action left out
+        let bindings = Bindings::parse("Hold Space").unwrap();
+        assert_eq!(bindings.action(Keycode::Space), Some(Action::Hold));
+        assert_eq!(bindings.keys(Action::HardDrop).next(), Some(Keycode::LCtrl));
+    }
+
+    #[test]
+    fn broken_bindings_are_rejected() {
+        let error = |text| Bindings::parse(text).unwrap_err();
+        assert_eq!(error("Jump Space"), "line 1: unknown action 'Jump'");
+        assert_eq!(error("Hold C\n\nHold X"), "line 3: 'Hold' given twice");
+        assert_eq!(error("Hold Q"), "line 1: unknown key 'Q'");
+        assert_eq!(error("Pause F3"), "line 1: 'F3' opens the controls screen");
+        assert_eq!(error("Hold C\nPause C"), "line 2: 'C' bound twice");
+        assert_eq!(error("Hold C, C"), "line 1: 'C' bound twice");
+    }
+}
@@ -0,0 +1,123 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variables overriding the directories, below the command line options.
+const CONFIG_DIR_VAR: &str = "TETRIS_CONFIG_DIR";
+const DATA_DIR_VAR: &str = "TETRIS_DATA_DIR";
+const ASSETS_DIR_VAR: &str = "TETRIS_ASSETS_DIR";
+
+/// Where the player's files are kept: settings and controls in `config`,
+/// scores, saved games and replays in `data`.
+pub struct Dirs {
+    pub config: PathBuf,
+    pub data: PathBuf,
+}
+
+fn var_dir(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+fn home_dir() -> Option<PathBuf> {
+    var_dir("HOME").or_else(|| var_dir("USERPROFILE"))
+}
+
+/// `$XDG_CONFIG_HOME`, `~/Library/Application Support` or `%APPDATA%`,
+/// depending on the platform.
+fn platform_config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        var_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        home_dir().map(|home| home.join("Library").join("Application Support"))
+    } else {
+        var_dir("XDG_CONFIG_HOME").or_else(|| home_dir().map(|home| home.join(".config")))
+    }
+}
+
+/// `$XDG_DATA_HOME`, `~/Library/Application Support` or `%LOCALAPPDATA%`,
+/// depending on the platform.
+fn platform_data_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        var_dir("LOCALAPPDATA").or_else(|| var_dir("APPDATA"))
+    } else if cfg!(target_os = "macos") {
+        home_dir().map(|home| home.join("Library").join("Application Support"))
+    } else {
+        var_dir("XDG_DATA_HOME")
+            .or_else(|| home_dir().map(|home| home.join(".local").join("share")))
+    }
+}
+
+impl Dirs {
+    /// Picks the directories: `config` and `data` when given, from the
+    /// command line, then the environment, then the platform's own.
+    pub fn new(config: Option<PathBuf>, data: Option<PathBuf>) -> Result<Dirs, String> {
+        let config = config
+            .or_else(|| var_dir(CONFIG_DIR_VAR))
+            .or_else(|| platform_config_dir().map(|dir| dir.join("tetris")))
+            .ok_or("can't find a config directory, use --config-dir")?;
+        let data = data
+            .or_else(|| var_dir(DATA_DIR_VAR))
+            .or_else(|| platform_data_dir().map(|dir| dir.join("tetris")))
+            .ok_or("can't find a data directory, use --data-dir")?;
+        Ok(Dirs { config, data })
+    }
+
+    /// Makes the directories, if they don't exist yet.
+    pub fn create(&self) -> Result<(), String> {
+        for dir in [&self.config, &self.data, &self.replays()].iter() {
+            fs::create_dir_all(dir)
+                .map_err(|err| format!("can't create {}: {}", dir.display(), err))?;
+        }
+        Ok(())
+    }
+
+    pub fn replays(&self) -> PathBuf {
+        self.data.join("replays")
+    }
+
+    /// Where a replay file given on the command line goes: bare file names
+    /// are taken in the replays directory, paths as they are.
+    pub fn replay(&self, file: &str) -> PathBuf {
+        let path = Path::new(file);
+        match path.parent() {
+            Some(parent) if parent != Path::new("") => path.to_path_buf(),
+            _ => self.replays().join(path),
+        }
+    }
+}
+
+/// Copies `name` from where older versions kept it, next to the game
+/// sources, into `dir`, unless it is already there.
+pub fn migrate(name: &str, dir: &Path) {
+    let target = dir.join(name);
+    let old = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.ancestors().nth(3).map(|root| root.join(name)));
+    if let Some(old) = old.filter(|old| old.exists() && !target.exists()) {
+        match fs::copy(&old, &target) {
+            Ok(_) => println!("Copied {} to {}", old.display(), target.display()),
+            Err(err) => eprintln!("Couldn't copy {}: {}", old.display(), err),
+        }
+    }
+}
+
+/// The directory holding the game's font: `$TETRIS_ASSETS_DIR`, `assets`
+/// next to the executable or above it, or where the sources were built.
+pub fn assets_dir() -> PathBuf {
+    let exe = env::current_exe().ok();
+    let mut candidates = var_dir(ASSETS_DIR_VAR).into_iter().collect::<Vec<_>>();
+    if let Some(ref exe) = exe {
+        // three levels up is the crate, when run from target/<profile>
+        candidates.extend(
+            exe.ancestors()
+                .skip(1)
+                .take(3)
+                .map(|dir| dir.join("assets")),
+        );
+    }
+    candidates.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("assets"));
+    let found = candidates.iter().find(|dir| dir.is_dir()).cloned();
+    found.unwrap_or_else(|| candidates.swap_remove(0))
+}
@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+
+/// Most ticks `FixedStep` makes up for at once: after a longer stall, the
+/// game slows down rather than playing seconds of gravity in a single frame.
+pub const MAX_CATCH_UP: u32 = 15;
+
+/// Tells how much time went by, so that the game loop knows how many ticks are due.
+pub trait Clock {
+    /// Time elapsed since the clock started.
+    fn now(&self) -> Duration;
+}
+
+/// The system's monotonic clock.
+#[derive(Debug)]
+pub struct RealClock {
+    start: Instant,
+}
+
+impl RealClock {
+    pub fn new() -> RealClock {
+        RealClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for RealClock {
+    fn default() -> Self {
+        RealClock::new()
+    }
+}
+
+impl Clock for RealClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A clock that only moves when told to, for tests and tools.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: Duration,
+}
+
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock::default()
+    }
+
+    pub fn advance(&mut self, by: Duration) {
+        self.now += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now
+    }
+}
+
+/// Splits the time a clock tells into ticks of fixed length.
+#[derive(Debug)]
+pub struct FixedStep {
+    rate: u32,
+    /// Ticks handed out so far, skipped ones included.
+    ticks: u64,
+}
+
+impl FixedStep {
+    /// Starts counting `rate` ticks per second from the clock's current time.
+    pub fn new(rate: u32, clock: &dyn Clock) -> FixedStep {
+        let mut step = FixedStep { rate, ticks: 0 };
+        step.ticks = step.total(clock);
+        step
+    }
+
+    fn total(&self, clock: &dyn Clock) -> u64 {
+        (clock.now().as_nanos() * self.rate as u128 / 1_000_000_000) as u64
+    }
+
+    /// Number of ticks to run since the last call, at most `MAX_CATCH_UP`.
+    pub fn due(&mut self, clock: &dyn Clock) -> u32 {
+        let total = self.total(clock);
+        let due = total.saturating_sub(self.ticks);
+        self.ticks = total;
+        due.min(MAX_CATCH_UP as u64) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_follow_the_clock() {
+        let mut clock = ManualClock::new();
+        clock.advance(Duration::from_millis(500));
+        let mut step = FixedStep::new(60, &clock);
+        assert_eq!(step.due(&clock), 0);
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(step.due(&clock), 0);
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(step.due(&clock), 1);
+        // a slow frame is caught up with
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(step.due(&clock), 6);
+        assert_eq!(step.due(&clock), 0);
+    }
+
+    #[test]
+    fn catching_up_is_capped() {
+        let mut clock = ManualClock::new();
+        let mut step = FixedStep::new(60, &clock);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(step.due(&clock), MAX_CATCH_UP);
+        // the ticks skipped are not made up for later
+        assert_eq!(step.due(&clock), 0);
+        clock.advance(Duration::from_millis(1000 / 60 + 1));
+        assert_eq!(step.due(&clock), 1);
+    }
+}
@@ -0,0 +1,156 @@
+use super::FRAME_RATE;
+use std::fmt::Debug;
+
+/// Speed pieces fall at, in cells per frame, counted in `CELL`ths of a cell
+/// so that fractional speeds stay exact enough for pieces given a number of
+/// frames per row to fall on the expected frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Gravity(u64);
+
+impl Gravity {
+    /// Fractions a cell is divided into.
+    pub const CELL: u64 = 1 << 32;
+    /// One row per frame.
+    pub const ONE_G: Gravity = Gravity(Gravity::CELL);
+    /// 20G: pieces go straight down to the floor.
+    pub const MAX: Gravity = Gravity(20 * Gravity::CELL);
+
+    /// Gravity of `cells` per frame, up to `MAX`.
+    pub fn cells(cells: f64) -> Gravity {
+        let fraction = (cells.max(0.0) * Gravity::CELL as f64).ceil();
+        Gravity((fraction as u64).min(Gravity::MAX.0))
+    }
+
+    /// Gravity of a row every `frames` frames.
+    pub fn every(frames: u32) -> Gravity {
+        Gravity(Gravity::CELL.div_ceil(frames.max(1) as u64))
+    }
+
+    /// `CELL`ths of a cell fallen per frame.
+    pub fn fraction(self) -> u64 {
+        self.0
+    }
+
+    pub fn cells_per_frame(self) -> f64 {
+        self.0 as f64 / Gravity::CELL as f64
+    }
+
+    /// Gravity `factor` times faster, up to `MAX`.
+    pub fn times(self, factor: u32) -> Gravity {
+        Gravity(self.0.saturating_mul(factor as u64).min(Gravity::MAX.0))
+    }
+}
+
+/// How fast pieces fall, level after level.
+pub trait GravityCurve: Debug {
+    fn gravity(&self, level: u32) -> Gravity;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum GravityKind {
+    /// The guideline's formula, reaching 20G around level 19.
+    #[default]
+    Guideline,
+    /// The NES frame table, levels counted from 0 as the NES does.
+    Nes,
+}
+
+impl GravityKind {
+    pub const ALL: [GravityKind; 2] = [GravityKind::Guideline, GravityKind::Nes];
+
+    pub fn create(self) -> Box<dyn GravityCurve> {
+        match self {
+            GravityKind::Guideline => Box::new(GuidelineGravity),
+            GravityKind::Nes => Box::new(NesGravity),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GuidelineGravity;
+
+impl GravityCurve for GuidelineGravity {
+    /// A row every `(0.8 - (level - 1) * 0.007) ^ (level - 1)` seconds.
+    fn gravity(&self, level: u32) -> Gravity {
+        let level = level.max(1) as f64 - 1.0;
+        let base = 0.8 - level * 0.007;
+        if base <= 0.0 {
+            return Gravity::MAX;
+        }
+        let seconds = base.powf(level);
+        Gravity::cells(1.0 / (seconds * FRAME_RATE as f64))
+    }
+}
+
+/// Frames a piece takes to fall one row on the NES, from level 0; the
+/// last value goes for every level after.
+const NES_FRAMES: [u32; 30] = [
+    48, 43, 38, 33, 28, 23, 18, 13, 8, 6, 5, 5, 5, 4, 4, 4, 3, 3, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+    1,
+];
+
+#[derive(Debug)]
+pub struct NesGravity;
+
+impl GravityCurve for NesGravity {
+    fn gravity(&self, level: u32) -> Gravity {
+        Gravity::every(NES_FRAMES[(level as usize).min(NES_FRAMES.len() - 1)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Rules, Tetris};
+
+    /// Frames `gravity` takes to pull a piece down one row.
+    fn frames_per_row(gravity: Gravity) -> u32 {
+        let mut progress = 0;
+        let mut frames = 0;
+        while progress < Gravity::CELL {
+            progress += gravity.fraction();
+            frames += 1;
+        }
+        frames
+    }
+
+    #[test]
+    fn nes_levels_follow_the_frame_table() {
+        let nes = GravityKind::Nes.create();
+        let frames = [0, 1, 8, 9, 10, 18, 19, 28, 29, 100]
+            .iter()
+            .map(|&level| frames_per_row(nes.gravity(level)))
+            .collect::<Vec<_>>();
+        assert_eq!(frames, [48, 43, 8, 6, 5, 3, 2, 2, 1, 1]);
+    }
+
+    #[test]
+    fn guideline_speeds_up_to_20g() {
+        let guideline = GravityKind::Guideline.create();
+        assert_eq!(frames_per_row(guideline.gravity(1)), 60);
+        assert_eq!(guideline.gravity(0), guideline.gravity(1));
+        let speeds = (1..=20).map(|level| guideline.gravity(level));
+        assert!(speeds.collect::<Vec<_>>().windows(2).all(|w| w[0] <= w[1]));
+        assert!(guideline.gravity(15) > Gravity::ONE_G);
+        assert_eq!(guideline.gravity(20), Gravity::MAX);
+        assert_eq!(Gravity::ONE_G.times(100), Gravity::MAX);
+        assert_eq!(Gravity::cells(0.5), Gravity::every(2));
+    }
+
+    #[test]
+    fn pieces_fall_to_the_floor_at_20g() {
+        let rules = Rules {
+            start_level: 20,
+            ..Rules::default()
+        };
+        let mut tetris = Tetris::with_rules(rules, 1);
+        tetris.tick();
+        let spawned = tetris.current_piece.as_ref().unwrap().y;
+        assert!(!tetris.is_grounded());
+        tetris.tick();
+        // many rows in one tick, right down to the floor
+        let landed = tetris.current_piece.as_ref().unwrap().y;
+        assert!(landed - spawned > 10);
+        assert!(tetris.is_grounded());
+    }
+}
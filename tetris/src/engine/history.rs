@@ -0,0 +1,149 @@
+use super::replay::Replay;
+use super::{InputEvent, Tetris};
+
+/// Frames between two snapshots: seeking replays at most this many frames.
+pub const SNAPSHOT_INTERVAL: u64 = 60;
+
+/// A game as it is being played, kept so that it can be reviewed afterwards:
+/// snapshots taken every `SNAPSHOT_INTERVAL` frames, and the inputs in between.
+#[derive(Debug, Clone)]
+pub struct History {
+    pub replay: Replay,
+    /// The game as it was when the history started.
+    start: Tetris,
+    snapshots: Vec<Tetris>,
+    /// Frame each piece came into play on, in order.
+    pub spawns: Vec<u64>,
+    /// Frames lines got cleared on, with how many.
+    pub clears: Vec<(u64, u32)>,
+    /// Last frame played.
+    pub end: u64,
+}
+
+impl History {
+    /// Starts keeping the history of a game, from where it is now.
+    pub fn new(tetris: &Tetris) -> History {
+        History {
+            replay: Replay::new(tetris),
+            start: tetris.clone(),
+            snapshots: Vec::new(),
+            spawns: Vec::new(),
+            clears: Vec::new(),
+            end: tetris.frame,
+        }
+    }
+
+    /// Advances the game by one frame, as `Tetris::tick` does.
+    pub fn tick(&mut self, tetris: &mut Tetris) {
+        if !tetris.is_pause()
+            && !tetris.is_game_over()
+            && tetris.frame.is_multiple_of(SNAPSHOT_INTERVAL)
+        {
+            // the frame is over: its inputs are all in
+            if self.snapshots.last().map(|snapshot| snapshot.frame) != Some(tetris.frame) {
+                self.snapshots.push(tetris.clone());
+            }
+        }
+        self.track(tetris, |_, tetris| tetris.tick());
+    }
+
+    /// Applies a key press or release, as `Replay::handle` does.
+    pub fn handle(&mut self, tetris: &mut Tetris, event: InputEvent) -> bool {
+        self.track(tetris, |replay, tetris| replay.handle(tetris, event))
+    }
+
+    /// Ends the game on the player's request, as `Replay::quit` does.
+    pub fn quit(&mut self, tetris: &mut Tetris) {
+        self.replay.quit(tetris);
+    }
+
+    /// Applies what `replay` recorded on the frame `tetris` just reached,
+    /// through the same path as the player's inputs.
+    pub fn feed(&mut self, tetris: &mut Tetris, replay: &Replay) {
+        for event in replay.inputs_at(tetris.frame) {
+            self.handle(tetris, event);
+        }
+        if replay.quit == Some(tetris.frame) {
+            self.quit(tetris);
+        }
+    }
+
+    /// Runs `action` on the game, and notes the pieces and line clears it brought.
+    fn track<T>(
+        &mut self,
+        tetris: &mut Tetris,
+        action: impl FnOnce(&mut Replay, &mut Tetris) -> T,
+    ) -> T {
+        let (pieces, lines) = (tetris.nb_pieces, tetris.nb_lines);
+        let result = action(&mut self.replay, tetris);
+        if tetris.nb_pieces > pieces {
+            self.spawns.push(tetris.frame);
+        }
+        if tetris.nb_lines > lines {
+            self.clears.push((tetris.frame, tetris.nb_lines - lines));
+        }
+        self.end = tetris.frame;
+        result
+    }
+
+    /// The game as it was at the end of `frame`.
+    pub fn seek(&self, frame: u64) -> Tetris {
+        let frame = frame.min(self.end);
+        let index = self
+            .snapshots
+            .partition_point(|snapshot| snapshot.frame <= frame);
+        let mut tetris = match index {
+            0 => {
+                // keys may have been pressed before the first tick
+                let mut tetris = self.start.clone();
+                self.replay.feed(&mut tetris);
+                tetris
+            }
+            _ => self.snapshots[index - 1].clone(),
+        };
+        self.forward(&mut tetris, frame);
+        tetris
+    }
+
+    /// Plays `tetris`, taken from this history, up to the end of `frame`,
+    /// or until it stops moving on, paused or over.
+    pub fn forward(&self, tetris: &mut Tetris, frame: u64) {
+        while tetris.frame < frame.min(self.end) {
+            let before = tetris.frame;
+            tetris.tick();
+            if tetris.frame == before {
+                break;
+            }
+            self.replay.feed(tetris);
+        }
+    }
+
+    /// Frame the piece numbered `piece` came into play on, counting from 1.
+    pub fn piece_frame(&self, piece: u32) -> Option<u64> {
+        match piece {
+            0 => None,
+            _ => self.spawns.get(piece as usize - 1).copied(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeking_stops_on_a_paused_game() {
+        let mut tetris = Tetris::new(1);
+        for _ in 0..100 {
+            tetris.tick();
+        }
+        tetris.toggle_pause();
+        let mut history = History::new(&tetris);
+        tetris.toggle_pause();
+        for _ in 0..30 {
+            history.tick(&mut tetris);
+        }
+        assert_eq!(history.seek(110).frame, 100);
+        assert_eq!(history.seek(125).frame, 125);
+    }
+}
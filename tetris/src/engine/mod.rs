@@ -0,0 +1,1677 @@
+pub mod clock;
+pub mod gravity;
+pub mod history;
+pub mod mode;
+pub mod randomizer;
+pub mod replay;
+pub mod rotation;
+pub mod save;
+pub mod scoring;
+pub mod tetrimino;
+use self::gravity::{Gravity, GravityCurve, GravityKind};
+use self::mode::{format_time, Delays, GameMode, GameModeKind, Stat, CHEESE_HEIGHT};
+use self::randomizer::{Randomizer, RandomizerKind, Rng};
+use self::rotation::{RotationKind, RotationSystem};
+use self::scoring::{Clear, Scoring, ScoringKind, TSpin};
+use self::tetrimino::{is_blocked, PieceKind, Tetrimino};
+use std::fmt::{Debug, Write};
+
+/// Number of times per second `Tetris::tick` is meant to be called.
+pub const FRAME_RATE: u32 = 60;
+/// Highest level a game can start at; gravity curves are at their fastest by then.
+pub const MAX_LEVEL: u32 = 20;
+/// Value of garbage cells in `Tetris::game_map`, after those of the 7 pieces.
+pub const GARBAGE: u8 = 8;
+/// Largest playfield, vanish zone apart, and vanish zone rules can ask for.
+pub const MAX_SIZE: usize = 100;
+pub const MAX_HIDDEN_ROWS: usize = 100;
+pub const LEVEL_LINES: [u32; 10] = [20, 40, 60, 80, 100, 120, 140, 160, 180, 200];
+
+/// Finds which of `variants` is called `name`, as printed by `Debug`.
+pub fn parse_variant<T: Debug + Copy>(variants: &[T], name: &str) -> Option<T> {
+    variants
+        .iter()
+        .copied()
+        .find(|variant| format!("{:?}", variant) == name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Running,
+    Pause,
+    GameOver(GameOverReason),
+}
+
+/// What a running game is busy with: a piece in play, or the delays
+/// between two pieces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    /// The current piece falls with gravity.
+    Falling,
+    /// The current piece rests on the ground, until the lock delay is over.
+    Locking,
+    /// Rows just cleared stay on show, before the stack above comes down.
+    LineClear,
+    /// Entry delay, or ARE: waiting for the next piece to come into play.
+    Entry,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [
+        Phase::Falling,
+        Phase::Locking,
+        Phase::LineClear,
+        Phase::Entry,
+    ];
+}
+
+/// Why a game ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameOverReason {
+    /// A new piece overlapped the stack where it spawns.
+    BlockOut,
+    /// A piece locked entirely above the visible playfield.
+    LockOut,
+    /// A piece locked with some of its blocks above the visible playfield.
+    PartialLockOut,
+    /// Blocks got pushed above the top of the vanish zone.
+    TopOut,
+    /// The player gave up.
+    Quit,
+    /// The game mode's goal was reached.
+    GoalReached,
+    /// The game mode's time ran out.
+    TimeUp,
+}
+
+impl GameOverReason {
+    pub fn label(self) -> &'static str {
+        match self {
+            GameOverReason::BlockOut => "Block Out",
+            GameOverReason::LockOut => "Lock Out",
+            GameOverReason::PartialLockOut => "Partial Lock Out",
+            GameOverReason::TopOut => "Top Out",
+            GameOverReason::Quit => "Quit",
+            GameOverReason::GoalReached => "Finished",
+            GameOverReason::TimeUp => "Time Up",
+        }
+    }
+}
+
+/// The actions a player (or a bot) can feed to `Tetris::step`, or hold down
+/// with `Tetris::press`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input {
+    MoveLeft,
+    MoveRight,
+    SoftDrop,
+    RotateCW,
+    RotateCCW,
+    HardDrop,
+    Hold,
+}
+
+/// A key being pressed or released, as the engine sees it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    Press(Input),
+    Release(Input),
+}
+
+impl Input {
+    pub const ALL: [Input; 7] = [
+        Input::MoveLeft,
+        Input::MoveRight,
+        Input::SoftDrop,
+        Input::RotateCW,
+        Input::RotateCCW,
+        Input::HardDrop,
+        Input::Hold,
+    ];
+}
+
+/// What gives a grounded piece more time before it locks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockReset {
+    /// Moves and rotations restart the lock delay, up to `Rules::move_reset_limit` times.
+    Move,
+    /// Only reaching a new lowest row restarts the lock delay.
+    Step,
+    /// Every move or rotation restarts the lock delay, without limit.
+    Infinity,
+}
+
+impl LockReset {
+    pub const ALL: [LockReset; 3] = [LockReset::Move, LockReset::Step, LockReset::Infinity];
+}
+
+/// Keys held down, for auto shift and soft drop.
+#[derive(Debug, Clone, Copy, Default)]
+struct Held {
+    left: bool,
+    right: bool,
+    soft_drop: bool,
+    /// Direction auto shifting: the last one pressed among those held.
+    shift: Option<Input>,
+    /// Ticks the shift direction has been held for.
+    das_timer: u32,
+    /// Ticks auto shift still waits for, after a rotation.
+    das_cut_timer: u32,
+    /// Rotation pressed between two pieces, applied as the next one spawns (IRS).
+    initial_rotation: Option<Input>,
+    /// Whether hold was pressed between two pieces, to hold the next one as
+    /// it spawns (IHS).
+    initial_hold: bool,
+}
+
+/// The rule set a game is played with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rules {
+    /// Visible playfield size, in cells.
+    pub width: usize,
+    pub height: usize,
+    /// Rows of vanish zone above the visible playfield, where pieces spawn.
+    /// There must be at least 2 for pieces to fit.
+    pub hidden_rows: usize,
+    pub randomizer: RandomizerKind,
+    pub rotation: RotationKind,
+    pub scoring: ScoringKind,
+    /// Whether the current piece can be put aside in the hold slot.
+    pub hold: bool,
+    /// Whether pieces can be hard dropped.
+    pub hard_drop: bool,
+    /// Whether the player gets to see where the piece would land.
+    pub ghost: bool,
+    /// Ticks a grounded piece waits before locking; 0 to lock it as soon as
+    /// gravity pulls it down again, as on the NES.
+    pub lock_delay: u32,
+    pub lock_reset: LockReset,
+    /// Number of lock delay restarts allowed by `LockReset::Move` on a given row.
+    pub move_reset_limit: u32,
+    /// Whether a piece locking entirely in the vanish zone ends the game.
+    pub lock_out: bool,
+    /// Whether a piece locking with any block in the vanish zone ends the game.
+    pub partial_lock_out: bool,
+    /// Delayed auto shift: ticks a move key is held before it starts repeating.
+    pub das: u32,
+    /// Auto repeat rate: ticks between two repeated moves, 0 to go straight to the wall.
+    pub arr: u32,
+    /// Ticks auto shift waits after a rotation, 0 for none.
+    pub das_cut: u32,
+    /// How many times faster than gravity a held soft drop makes pieces fall.
+    pub soft_drop_factor: u32,
+    /// Ticks between two rows of garbage rising from the bottom, 0 for none.
+    pub rising_garbage: u32,
+    /// Level the game starts at, within the game mode's `start_levels`.
+    pub start_level: u32,
+    /// How fast pieces fall at each level, unless the game mode has its own curve.
+    pub gravity: GravityKind,
+    pub mode: GameModeKind,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            width: 10,
+            height: 20,
+            hidden_rows: 20,
+            randomizer: RandomizerKind::default(),
+            rotation: RotationKind::default(),
+            scoring: ScoringKind::default(),
+            hold: true,
+            hard_drop: true,
+            ghost: true,
+            lock_delay: 30,
+            lock_reset: LockReset::Move,
+            move_reset_limit: 15,
+            lock_out: true,
+            partial_lock_out: false,
+            das: 10,
+            arr: 2,
+            das_cut: 0,
+            soft_drop_factor: 20,
+            rising_garbage: 0,
+            start_level: 1,
+            gravity: GravityKind::default(),
+            mode: GameModeKind::default(),
+        }
+    }
+}
+
+impl Rules {
+    /// Writes every rule as a `name value` line, the way `set` reads them back.
+    pub fn write(&self, out: &mut String) {
+        let _ = writeln!(out, "width {}", self.width);
+        let _ = writeln!(out, "height {}", self.height);
+        let _ = writeln!(out, "hidden_rows {}", self.hidden_rows);
+        let _ = writeln!(out, "randomizer {:?}", self.randomizer);
+        let _ = writeln!(out, "rotation {:?}", self.rotation);
+        let _ = writeln!(out, "scoring {:?}", self.scoring);
+        let _ = writeln!(out, "hold {}", self.hold);
+        let _ = writeln!(out, "hard_drop {}", self.hard_drop);
+        let _ = writeln!(out, "ghost {}", self.ghost);
+        let _ = writeln!(out, "lock_delay {}", self.lock_delay);
+        let _ = writeln!(out, "lock_reset {:?}", self.lock_reset);
+        let _ = writeln!(out, "move_reset_limit {}", self.move_reset_limit);
+        let _ = writeln!(out, "lock_out {}", self.lock_out);
+        let _ = writeln!(out, "partial_lock_out {}", self.partial_lock_out);
+        let _ = writeln!(out, "das {}", self.das);
+        let _ = writeln!(out, "arr {}", self.arr);
+        let _ = writeln!(out, "das_cut {}", self.das_cut);
+        let _ = writeln!(out, "soft_drop_factor {}", self.soft_drop_factor);
+        let _ = writeln!(out, "rising_garbage {}", self.rising_garbage);
+        let _ = writeln!(out, "start_level {}", self.start_level);
+        let _ = writeln!(out, "gravity {:?}", self.gravity);
+        let _ = writeln!(out, "mode {:?}", self.mode);
+    }
+
+    /// Sets the rule called `name` from its text value.
+    /// Returns Ok(false) if there is no such rule.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<bool, String> {
+        fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("invalid value '{}'", value))
+        }
+        fn variant<T: Debug + Copy>(variants: &[T], value: &str) -> Result<T, String> {
+            parse_variant(variants, value).ok_or_else(|| format!("unknown variant '{}'", value))
+        }
+        match name {
+            "width" => self.width = parse(value)?,
+            "height" => self.height = parse(value)?,
+            "hidden_rows" => self.hidden_rows = parse(value)?,
+            "randomizer" => self.randomizer = variant(&RandomizerKind::ALL, value)?,
+            "rotation" => self.rotation = variant(&RotationKind::ALL, value)?,
+            "scoring" => self.scoring = variant(&ScoringKind::ALL, value)?,
+            "hold" => self.hold = parse(value)?,
+            "hard_drop" => self.hard_drop = parse(value)?,
+            "ghost" => self.ghost = parse(value)?,
+            "lock_delay" => self.lock_delay = parse(value)?,
+            "lock_reset" => self.lock_reset = variant(&LockReset::ALL, value)?,
+            "move_reset_limit" => self.move_reset_limit = parse(value)?,
+            "lock_out" => self.lock_out = parse(value)?,
+            "partial_lock_out" => self.partial_lock_out = parse(value)?,
+            "das" => self.das = parse(value)?,
+            "arr" => self.arr = parse(value)?,
+            "das_cut" => self.das_cut = parse(value)?,
+            "soft_drop_factor" => self.soft_drop_factor = parse(value)?,
+            "rising_garbage" => self.rising_garbage = parse(value)?,
+            "start_level" => self.start_level = parse(value)?,
+            "gravity" => self.gravity = variant(&GravityKind::ALL, value)?,
+            "mode" => self.mode = variant(&GameModeKind::ALL, value)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// The gravity curve games are played with: the game mode's own, or
+    /// the one the rules pick.
+    fn gravity_curve(&self) -> Box<dyn GravityCurve> {
+        let curve = self.mode.create().gravity();
+        curve.unwrap_or_else(|| self.gravity.create())
+    }
+
+    /// Checks the rules describe a playable game.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width < 4 || self.height < 4 {
+            return Err(format!(
+                "playfield {}x{} is too small",
+                self.width, self.height
+            ));
+        }
+        if self.width > MAX_SIZE || self.height > MAX_SIZE {
+            return Err(format!(
+                "playfield {}x{} is too large",
+                self.width, self.height
+            ));
+        }
+        if self.soft_drop_factor == 0 {
+            return Err("the soft drop factor can't be 0".to_string());
+        }
+        if self.hidden_rows < 2 {
+            return Err("there must be at least 2 hidden rows".to_string());
+        }
+        if self.hidden_rows > MAX_HIDDEN_ROWS {
+            return Err(format!(
+                "there can't be more than {} hidden rows",
+                MAX_HIDDEN_ROWS
+            ));
+        }
+        if self.hidden_rows.checked_add(self.height).is_none() {
+            return Err("too many rows".to_string());
+        }
+        let (min, max) = self.mode.create().start_levels();
+        if self.start_level < min || self.start_level > max {
+            return Err(format!("invalid start level {}", self.start_level));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Tetris {
+    pub state: State,
+    pub game_map: Vec<Vec<u8>>,
+    pub current_level: u32,
+    pub score: u32,
+    pub nb_lines: u32,
+    /// Pieces that came into play so far.
+    pub nb_pieces: u32,
+    /// Consecutive pieces that cleared lines, minus one; None once the chain broke.
+    pub combo: Option<u32>,
+    /// Whether the last line clear was a difficult one, ready for a back-to-back.
+    pub back_to_back: bool,
+    /// What the last locked piece achieved.
+    pub last_clear: Option<Clear>,
+    pub current_piece: Option<Tetrimino>,
+    pub next_piece: Option<Tetrimino>,
+    pub hold_piece: Option<Tetrimino>,
+    /// Only one hold is allowed until the current piece locks.
+    pub can_hold: bool,
+    /// Lock delay restarts used since the piece reached `lowest_y`.
+    lock_resets: u32,
+    lowest_y: isize,
+    pub rules: Rules,
+    pub seed: u64,
+    /// Ticks played so far, pauses excluded; replays call them frames.
+    pub frame: u64,
+    phase: Phase,
+    /// Ticks left in the line clear or entry delay.
+    delay_timer: u32,
+    /// Rows of the map being cleared during `Phase::LineClear`, top first.
+    cleared_rows: Vec<usize>,
+    /// Ticks since the current piece landed or had its lock delay restarted.
+    fall_timer: u32,
+    /// How far the current piece got towards falling another row, in
+    /// `Gravity::CELL`ths of a cell.
+    fall_progress: u64,
+    /// Garbage lines cleared so far.
+    pub garbage_cleared: u32,
+    /// Rows of garbage to rise before the next piece comes in.
+    pending_garbage: u32,
+    /// Rows of garbage a cheese race still has to bring in.
+    cheese_left: u32,
+    /// Ticks since garbage last rose, with `Rules::rising_garbage`.
+    garbage_timer: u32,
+    /// Picks the holes in garbage rows, apart from the pieces so that the
+    /// preview stays right.
+    garbage_rng: Rng,
+    /// Keys held down, as told by `press` and `release`.
+    held: Held,
+    randomizer: Box<dyn Randomizer>,
+    rotation: Box<dyn RotationSystem>,
+    scoring: Box<dyn Scoring>,
+    mode: Box<dyn GameMode>,
+    gravity: Box<dyn GravityCurve>,
+}
+
+// the rotation system, scoring rules, game mode and gravity curve hold no state,
+// they are made again from the rules
+impl Clone for Tetris {
+    fn clone(&self) -> Tetris {
+        Tetris {
+            state: self.state,
+            game_map: self.game_map.clone(),
+            current_level: self.current_level,
+            score: self.score,
+            nb_lines: self.nb_lines,
+            nb_pieces: self.nb_pieces,
+            combo: self.combo,
+            back_to_back: self.back_to_back,
+            last_clear: self.last_clear,
+            current_piece: self.current_piece.clone(),
+            next_piece: self.next_piece.clone(),
+            hold_piece: self.hold_piece.clone(),
+            can_hold: self.can_hold,
+            lock_resets: self.lock_resets,
+            lowest_y: self.lowest_y,
+            rules: self.rules,
+            seed: self.seed,
+            frame: self.frame,
+            phase: self.phase,
+            delay_timer: self.delay_timer,
+            cleared_rows: self.cleared_rows.clone(),
+            fall_timer: self.fall_timer,
+            fall_progress: self.fall_progress,
+            garbage_cleared: self.garbage_cleared,
+            pending_garbage: self.pending_garbage,
+            cheese_left: self.cheese_left,
+            garbage_timer: self.garbage_timer,
+            garbage_rng: self.garbage_rng.clone(),
+            held: self.held,
+            randomizer: self.randomizer.clone(),
+            rotation: self.rules.rotation.create(),
+            scoring: self.rules.scoring.create(),
+            mode: self.rules.mode.create(),
+            gravity: self.rules.gravity_curve(),
+        }
+    }
+}
+
+impl Tetris {
+    pub fn new(seed: u64) -> Tetris {
+        Tetris::with_rules(Rules::default(), seed)
+    }
+
+    /// Starts a game played by `rules`, as the game mode adjusts them.
+    pub fn with_rules(rules: Rules, seed: u64) -> Tetris {
+        let rules = rules.mode.create().rules(rules);
+        let game_map = vec![vec![0; rules.width]; rules.hidden_rows + rules.height];
+        let mut tetris = Tetris {
+            state: State::Running,
+            game_map,
+            current_level: rules.start_level,
+            score: 0,
+            nb_lines: 0,
+            nb_pieces: 0,
+            combo: None,
+            back_to_back: false,
+            last_clear: None,
+            current_piece: None,
+            next_piece: None,
+            hold_piece: None,
+            can_hold: true,
+            lock_resets: 0,
+            lowest_y: 0,
+            rules,
+            seed,
+            frame: 0,
+            // the first piece comes in on the first tick
+            phase: Phase::Entry,
+            delay_timer: 0,
+            cleared_rows: Vec::new(),
+            fall_timer: 0,
+            fall_progress: 0,
+            garbage_cleared: 0,
+            pending_garbage: 0,
+            cheese_left: 0,
+            garbage_timer: 0,
+            garbage_rng: Rng::new(!seed),
+            held: Held::default(),
+            randomizer: rules.randomizer.create(seed),
+            rotation: rules.rotation.create(),
+            scoring: rules.scoring.create(),
+            mode: rules.mode.create(),
+            gravity: rules.gravity_curve(),
+        };
+        tetris.next_piece = Some(tetris.create_piece());
+        if let Some(lines) = tetris.mode.cheese_lines() {
+            tetris.cheese_left = lines;
+            tetris.refill_cheese();
+            tetris.raise_garbage();
+        }
+        tetris
+    }
+
+    /// Deals a new piece from the randomizer, ready to spawn.
+    fn create_piece(&mut self) -> Tetrimino {
+        let kind = PieceKind::from_index(self.randomizer.next_piece());
+        Tetrimino::new(
+            kind,
+            &*self.rotation,
+            self.rules.width,
+            self.rules.hidden_rows,
+        )
+    }
+
+    /// The next `count` pieces to come into play, the next piece first.
+    pub fn preview(&self, count: usize) -> Vec<Tetrimino> {
+        let mut randomizer = self.randomizer.clone();
+        let coming = (1..count).map(|_| {
+            Tetrimino::new(
+                PieceKind::from_index(randomizer.next_piece()),
+                &*self.rotation,
+                self.rules.width,
+                self.rules.hidden_rows,
+            )
+        });
+        self.next_piece
+            .iter()
+            .cloned()
+            .chain(coming)
+            .take(count)
+            .collect()
+    }
+
+    pub fn is_pause(&self) -> bool {
+        self.state == State::Pause
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.game_over_reason().is_some()
+    }
+
+    pub fn game_over_reason(&self) -> Option<GameOverReason> {
+        match self.state {
+            State::GameOver(reason) => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// Pauses or resumes the game. Keys held down stay held, as replays
+    /// don't see pauses; they can still be released while paused.
+    pub fn toggle_pause(&mut self) {
+        self.state = match self.state {
+            State::Running => State::Pause,
+            State::Pause => State::Running,
+            State::GameOver(reason) => State::GameOver(reason),
+        };
+    }
+
+    pub fn end_game(&mut self, reason: GameOverReason) {
+        self.current_piece = None;
+        self.state = State::GameOver(reason);
+    }
+
+    pub fn mode(&self) -> &dyn GameMode {
+        &*self.mode
+    }
+
+    /// Text for one of the stats the game mode shows.
+    pub fn stat(&self, stat: Stat) -> String {
+        match stat {
+            Stat::Score => format!("Score:{}", self.score),
+            Stat::Lines => format!("Lines:{}", self.nb_lines),
+            Stat::Level => format!("Level:{}", self.current_level),
+            Stat::Time => format!("Time:{}", format_time(self.frame)),
+            Stat::LinesLeft => {
+                let goal = self.mode.goal_lines().unwrap_or(0);
+                format!("Left:{}", goal.saturating_sub(self.nb_lines))
+            }
+            Stat::TimeLeft => {
+                let limit = self.mode.time_limit().unwrap_or(0);
+                format!("Time:{}", format_time(limit.saturating_sub(self.frame)))
+            }
+            Stat::GarbageLeft => {
+                let goal = self.mode.cheese_lines().unwrap_or(0);
+                format!("Left:{}", goal.saturating_sub(self.garbage_cleared))
+            }
+        }
+    }
+
+    /// Ends the game if the mode's goal is reached, or its time is up.
+    fn check_goal(&mut self) {
+        if self.state != State::Running {
+            return;
+        }
+        if matches!(self.mode.goal_lines(), Some(goal) if self.nb_lines >= goal)
+            || matches!(self.mode.cheese_lines(), Some(goal) if self.garbage_cleared >= goal)
+        {
+            self.end_game(GameOverReason::GoalReached);
+        } else if matches!(self.mode.time_limit(), Some(limit) if self.frame >= limit) {
+            self.end_game(GameOverReason::TimeUp);
+        }
+    }
+
+    /// Ends the game as the stack overflowed, unless the game mode clears
+    /// the playfield instead. Returns true if the game goes on.
+    fn top_out(&mut self, reason: GameOverReason) -> bool {
+        if !self.mode.survives_top_out() {
+            self.end_game(reason);
+            return false;
+        }
+        for line in self.game_map.iter_mut() {
+            line.iter_mut().for_each(|case| *case = 0);
+        }
+        true
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Ticks left before the line clear or entry delay is over.
+    pub fn delay_left(&self) -> u32 {
+        self.delay_timer
+    }
+
+    /// Rows being cleared while the game is in `Phase::LineClear`, as
+    /// indexes in `game_map`.
+    pub fn cleared_rows(&self) -> &[usize] {
+        &self.cleared_rows
+    }
+
+    pub fn delays(&self) -> Delays {
+        self.mode.delays()
+    }
+
+    /// Gravity at the current level.
+    pub fn level_gravity(&self) -> Gravity {
+        self.gravity.gravity(self.current_level)
+    }
+
+    /// Speed the current piece falls at: the level's gravity, sped up by
+    /// soft drop.
+    pub fn gravity(&self) -> Gravity {
+        if self.held.soft_drop {
+            self.level_gravity().times(self.rules.soft_drop_factor)
+        } else {
+            self.level_gravity()
+        }
+    }
+
+    /// Advances the game by one tick: makes the current piece fall as many
+    /// rows as gravity says, or lock once the lock delay is over, then goes
+    /// through the line clear and entry delays before the next piece comes in.
+    pub fn tick(&mut self) {
+        if self.state != State::Running {
+            return;
+        }
+        self.frame = self.frame.saturating_add(1);
+        if self.rules.rising_garbage > 0 {
+            self.garbage_timer = self.garbage_timer.saturating_add(1);
+            if self.garbage_timer >= self.rules.rising_garbage {
+                self.garbage_timer = 0;
+                self.pending_garbage += 1;
+            }
+        }
+        // auto shift charges between pieces too
+        self.auto_shift();
+        match self.phase {
+            Phase::Falling | Phase::Locking if self.is_grounded() => {
+                self.phase = Phase::Locking;
+                if self.rules.lock_delay == 0 {
+                    // the piece locks as gravity pulls it down again
+                    self.fall_progress += self.gravity().fraction();
+                    if self.fall_progress >= Gravity::CELL {
+                        self.fall_progress = 0;
+                        self.fall();
+                    }
+                } else {
+                    self.fall_progress = 0;
+                    self.fall_timer += 1;
+                    if self.fall_timer >= self.rules.lock_delay {
+                        self.fall();
+                        self.fall_timer = 0;
+                    }
+                }
+            }
+            Phase::Falling | Phase::Locking => {
+                self.phase = Phase::Falling;
+                self.apply_gravity();
+            }
+            Phase::LineClear => {
+                self.delay_timer = self.delay_timer.saturating_sub(1);
+                if self.delay_timer == 0 {
+                    self.collapse_rows();
+                    self.start_entry();
+                }
+            }
+            Phase::Entry => self.delay_timer = self.delay_timer.saturating_sub(1),
+        }
+        if self.phase == Phase::Entry && self.delay_timer == 0 {
+            self.enter_next_piece();
+        }
+        self.check_goal();
+    }
+
+    /// Brings the next piece into play, then holds and rotates it as asked
+    /// while it was on its way.
+    fn enter_next_piece(&mut self) {
+        if !self.spawn_piece() {
+            return;
+        }
+        self.phase = Phase::Falling;
+        self.fall_timer = 0;
+        self.fall_progress = 0;
+        if std::mem::take(&mut self.held.initial_hold) {
+            self.step(Input::Hold);
+        }
+        if let Some(rotation) = self.held.initial_rotation.take() {
+            self.step(rotation);
+        }
+    }
+
+    /// Starts the line clear delay if the piece that just locked cleared
+    /// rows, else the entry delay.
+    fn start_line_clear(&mut self) {
+        let delay = self.mode.delays().line_clear;
+        if self.cleared_rows.is_empty() || delay == 0 {
+            self.collapse_rows();
+            self.start_entry();
+        } else {
+            self.phase = Phase::LineClear;
+            self.delay_timer = delay;
+        }
+    }
+
+    /// Starts the entry delay, once the garbage due has risen.
+    fn start_entry(&mut self) {
+        self.phase = Phase::Entry;
+        self.delay_timer = self.mode.delays().entry;
+        self.refill_cheese();
+        self.raise_garbage();
+    }
+
+    /// Makes `rows` rows of garbage rise from the bottom, before the next
+    /// piece comes in.
+    pub fn add_garbage(&mut self, rows: u32) {
+        self.pending_garbage += rows;
+    }
+
+    /// Brings the cheese race's garbage back up to `CHEESE_HEIGHT` rows,
+    /// while there is some left.
+    fn refill_cheese(&mut self) {
+        let present = self
+            .game_map
+            .iter()
+            .filter(|line| line.contains(&GARBAGE))
+            .count() as u32;
+        let rows = CHEESE_HEIGHT.saturating_sub(present).min(self.cheese_left);
+        self.cheese_left -= rows;
+        self.pending_garbage += rows;
+    }
+
+    /// Pushes the stack up with the pending garbage: rows full of garbage
+    /// but for one random hole. Blocks pushed off the top end the game.
+    fn raise_garbage(&mut self) {
+        for _ in 0..std::mem::take(&mut self.pending_garbage) {
+            let top = self.game_map.remove(0);
+            let mut line = vec![GARBAGE; self.rules.width];
+            line[(self.garbage_rng.next_u64() % self.rules.width as u64) as usize] = 0;
+            self.game_map.push(line);
+            if top.iter().any(|case| *case != 0) && !self.top_out(GameOverReason::TopOut) {
+                return;
+            }
+        }
+    }
+
+    /// Makes the current piece fall the rows it went through this tick,
+    /// stopping on the ground where the lock delay starts.
+    fn apply_gravity(&mut self) {
+        self.fall_progress += self.gravity().fraction();
+        let rows = self.fall_progress / Gravity::CELL;
+        self.fall_progress %= Gravity::CELL;
+        for _ in 0..rows {
+            if self.is_grounded() {
+                break;
+            }
+            self.fall();
+            if self.held.soft_drop {
+                self.update_score(self.scoring.soft_drop(1));
+            }
+        }
+        if self.is_grounded() {
+            self.fall_progress = 0;
+            self.fall_timer = 0;
+            if self.out_of_resets() {
+                // landing back on a row it already used all its resets on
+                self.make_permanent();
+            }
+        }
+    }
+
+    /// Applies an input's key being pressed or released.
+    /// Returns true if the fall timer restarted.
+    pub fn handle(&mut self, event: InputEvent) -> bool {
+        match event {
+            InputEvent::Press(input) => self.press(input),
+            InputEvent::Release(input) => {
+                self.release(input);
+                false
+            }
+        }
+    }
+
+    /// Applies `input` once, as `step` does, and keeps it held down: moves then
+    /// repeat following `Rules::das` and `Rules::arr`, soft drop speeds gravity up.
+    /// Rotations and hold pressed between two pieces apply to the next one.
+    pub fn press(&mut self, input: Input) -> bool {
+        if self.state != State::Running {
+            return false;
+        }
+        if self.current_piece.is_none() {
+            match input {
+                Input::RotateCW | Input::RotateCCW => self.held.initial_rotation = Some(input),
+                Input::Hold => self.held.initial_hold = true,
+                _ => {}
+            }
+        }
+        match input {
+            Input::MoveLeft | Input::MoveRight => {
+                if input == Input::MoveLeft {
+                    self.held.left = true;
+                } else {
+                    self.held.right = true;
+                }
+                self.held.shift = Some(input);
+                self.held.das_timer = 0;
+            }
+            Input::SoftDrop => self.held.soft_drop = true,
+            Input::RotateCW | Input::RotateCCW => self.held.das_cut_timer = self.rules.das_cut,
+            Input::HardDrop | Input::Hold => {}
+        }
+        self.step(input)
+    }
+
+    /// Lets go of an input held by `press`, paused or not.
+    pub fn release(&mut self, input: Input) {
+        if self.is_game_over() {
+            return;
+        }
+        match input {
+            Input::MoveLeft => self.held.left = false,
+            Input::MoveRight => self.held.right = false,
+            Input::SoftDrop => self.held.soft_drop = false,
+            _ => return,
+        }
+        if self.held.shift == Some(input) {
+            // the other direction takes over if it's still held, charging again
+            self.held.shift = if self.held.left {
+                Some(Input::MoveLeft)
+            } else if self.held.right {
+                Some(Input::MoveRight)
+            } else {
+                None
+            };
+            self.held.das_timer = 0;
+        }
+    }
+
+    /// Repeats the held move once DAS is charged, every ARR ticks.
+    fn auto_shift(&mut self) {
+        let direction = match self.held.shift {
+            Some(direction) => direction,
+            None => return,
+        };
+        if self.held.das_cut_timer > 0 {
+            self.held.das_cut_timer -= 1;
+            return;
+        }
+        self.held.das_timer += 1;
+        if self.held.das_timer < self.rules.das {
+            return;
+        }
+        if self.rules.arr == 0 {
+            // straight to the wall
+            loop {
+                let x = self.current_piece.as_ref().map(|piece| piece.x);
+                self.step(direction);
+                if self.current_piece.as_ref().map(|piece| piece.x) == x {
+                    break;
+                }
+            }
+        } else if (self.held.das_timer - self.rules.das).is_multiple_of(self.rules.arr) {
+            self.step(direction);
+        }
+    }
+
+    /// Whether the current piece can't move down any further.
+    pub fn is_grounded(&self) -> bool {
+        match self.current_piece {
+            Some(ref piece) => !piece.test_position(
+                &self.game_map,
+                piece.current_state as usize,
+                piece.x,
+                piece.y + 1,
+            ),
+            None => false,
+        }
+    }
+
+    /// Moves the next piece into play; ends the game if it doesn't fit.
+    /// Returns true if a new piece was spawned.
+    pub fn spawn_piece(&mut self) -> bool {
+        if self.state != State::Running || self.current_piece.is_some() {
+            return false;
+        }
+        // we need to take ownership of the option value, to move it to current_piece
+        if let Some(current_piece) = self.next_piece.take() {
+            if !current_piece.test_current_position(&self.game_map)
+                && !self.top_out(GameOverReason::BlockOut)
+            {
+                // restore next piece
+                self.next_piece = Some(current_piece);
+                return false;
+            }
+            // consume next piece
+            self.lowest_y = current_piece.y;
+            self.lock_resets = 0;
+            self.nb_pieces = self.nb_pieces.saturating_add(1);
+            self.current_piece = Some(current_piece);
+            self.next_piece = Some(self.create_piece());
+        }
+        true
+    }
+
+    /// Applies one player input to the current piece.
+    /// Returns true if the fall timer restarted, which is also the case
+    /// when the piece got locked into the map.
+    pub fn step(&mut self, input: Input) -> bool {
+        let restart = self.apply(input);
+        if restart {
+            self.fall_timer = 0;
+            self.fall_progress = 0;
+        }
+        restart
+    }
+
+    fn apply(&mut self, input: Input) -> bool {
+        if self.state != State::Running || self.current_piece.is_none() {
+            return false;
+        }
+        if input == Input::Hold {
+            let held = self.can_hold && self.rules.hold;
+            self.hold();
+            return held;
+        }
+        if input == Input::HardDrop && !self.rules.hard_drop {
+            return false;
+        }
+        let was_grounded = self.is_grounded();
+        let mut moved = false;
+        let mut dropped = 0;
+        let mut make_permanent = false;
+        if let Some(ref mut piece) = self.current_piece {
+            let (x, y) = (piece.x, piece.y);
+            match input {
+                Input::MoveLeft => {
+                    moved = piece.change_position(&self.game_map, x - 1, y);
+                }
+                Input::MoveRight => {
+                    moved = piece.change_position(&self.game_map, x + 1, y);
+                }
+                Input::SoftDrop => {
+                    moved = piece.change_position(&self.game_map, x, y + 1);
+                }
+                Input::RotateCW => {
+                    moved = piece.rotate(&self.game_map, true, &*self.rotation);
+                }
+                Input::RotateCCW => {
+                    moved = piece.rotate(&self.game_map, false, &*self.rotation);
+                }
+                Input::HardDrop => {
+                    let mut new_y = y;
+                    while piece.change_position(&self.game_map, x, new_y + 1) {
+                        new_y += 1;
+                    }
+                    dropped = self.scoring.hard_drop((new_y - y) as u32);
+                    make_permanent = true;
+                }
+                Input::Hold => unreachable!(),
+            }
+        }
+        if input == Input::SoftDrop && moved {
+            dropped = self.scoring.soft_drop(1);
+        }
+        self.update_score(dropped);
+        if make_permanent {
+            self.make_permanent();
+            return true;
+        }
+        moved && self.piece_moved(was_grounded)
+    }
+
+    /// Updates the lock delay state after the current piece moved or rotated.
+    /// Returns true if the fall timer must restart.
+    fn piece_moved(&mut self, was_grounded: bool) -> bool {
+        let y = match self.current_piece {
+            Some(ref piece) => piece.y,
+            None => return false,
+        };
+        let grounded = self.is_grounded();
+        if y > self.lowest_y {
+            self.lowest_y = y;
+            self.lock_resets = 0;
+            return true;
+        }
+        if !was_grounded && !grounded {
+            return false;
+        }
+        // moves and rotations touching the ground, kicks up and landings
+        // included, all use up a reset
+        match self.rules.lock_reset {
+            LockReset::Infinity => true,
+            LockReset::Step => false,
+            LockReset::Move => {
+                if self.lock_resets < self.rules.move_reset_limit {
+                    self.lock_resets += 1;
+                    true
+                } else if grounded {
+                    // out of resets: the piece locks as soon as it touches the ground
+                    self.make_permanent();
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    /// Whether the current piece used all the lock delay restarts
+    /// `LockReset::Move` allows on its lowest row.
+    fn out_of_resets(&self) -> bool {
+        self.rules.lock_reset == LockReset::Move && self.lock_resets >= self.rules.move_reset_limit
+    }
+
+    /// Swaps the current piece with the held one, back in its spawn orientation.
+    /// Without a held piece yet, the next piece comes into play.
+    fn hold(&mut self) {
+        if !self.rules.hold || !self.can_hold {
+            return;
+        }
+        if let Some(piece) = self.current_piece.take() {
+            let held = Tetrimino::new(
+                piece.kind,
+                &*self.rotation,
+                self.rules.width,
+                self.rules.hidden_rows,
+            );
+            self.can_hold = false;
+            match self.hold_piece.replace(held) {
+                Some(current_piece) => {
+                    if current_piece.test_current_position(&self.game_map)
+                        || self.top_out(GameOverReason::BlockOut)
+                    {
+                        self.lowest_y = current_piece.y;
+                        self.lock_resets = 0;
+                        self.current_piece = Some(current_piece);
+                    }
+                }
+                None => {
+                    self.spawn_piece();
+                }
+            }
+        }
+    }
+
+    /// Makes the current piece fall by one row, as gravity does. A piece
+    /// already on the ground gets locked.
+    /// Returns true if the piece got locked into the map.
+    pub fn fall(&mut self) -> bool {
+        if self.state != State::Running {
+            return false;
+        }
+        let mut make_permanent = false;
+        if let Some(ref mut piece) = self.current_piece {
+            let x = piece.x;
+            let y = piece.y + 1;
+            if piece.change_position(&self.game_map, x, y) {
+                if y > self.lowest_y {
+                    self.lowest_y = y;
+                    self.lock_resets = 0;
+                }
+            } else {
+                make_permanent = true;
+            }
+        }
+        if make_permanent {
+            self.make_permanent();
+        }
+        make_permanent
+    }
+
+    /// Row where the current piece would land if hard dropped.
+    pub fn ghost_y(&self) -> Option<isize> {
+        self.current_piece.as_ref().map(|piece| {
+            let mut y = piece.y;
+            while piece.test_position(&self.game_map, piece.current_state as usize, piece.x, y + 1)
+            {
+                y += 1;
+            }
+            y
+        })
+    }
+
+    fn update_score(&mut self, to_add: u32) {
+        self.score = self.score.saturating_add(to_add);
+    }
+
+    fn increase_line(&mut self) {
+        self.nb_lines = self.nb_lines.saturating_add(1);
+        self.current_level = self.mode.level(self.rules.start_level, self.nb_lines);
+    }
+
+    /// Finds the complete rows, to be cleared once the line clear delay is
+    /// over, and returns how many there are.
+    fn check_lines(&mut self) -> u32 {
+        self.cleared_rows = (0..self.game_map.len())
+            .filter(|y| self.game_map[*y].iter().all(|x| *x != 0))
+            .collect();
+        self.cleared_rows.len() as u32
+    }
+
+    /// Removes the cleared rows and refills the map from the top.
+    fn collapse_rows(&mut self) {
+        for y in std::mem::take(&mut self.cleared_rows).into_iter().rev() {
+            let line = self.game_map.remove(y);
+            if line.contains(&GARBAGE) {
+                self.garbage_cleared = self.garbage_cleared.saturating_add(1);
+            }
+        }
+        while self.game_map.len() < self.rules.hidden_rows + self.rules.height {
+            self.increase_line();
+            self.game_map.insert(0, vec![0; self.rules.width]);
+        }
+    }
+
+    /// Checks whether the current piece, about to lock, makes a T-spin:
+    /// a T that just rotated with at least 3 of the 4 corners around its
+    /// centre filled. It's a mini unless both corners it points to are filled,
+    /// or it got there through the far SRS kick.
+    fn detect_tspin(&self) -> TSpin {
+        let piece = match self.current_piece {
+            Some(ref piece) if piece.kind == PieceKind::T => piece,
+            _ => return TSpin::None,
+        };
+        let kick = match piece.last_kick {
+            Some(kick) => kick,
+            None => return TSpin::None,
+        };
+        let shape = &piece.states[piece.current_state as usize];
+        let filled = |x: isize, y: isize| {
+            x >= 0
+                && y >= 0
+                && matches!(
+                    shape.get(y as usize).and_then(|line| line.get(x as usize)),
+                    Some(case) if *case != 0
+                )
+        };
+        let occupied = |x: isize, y: isize| is_blocked(&self.game_map, piece.x + x, piece.y + y);
+        const SIDES: [(isize, isize); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+        const CORNERS: [(isize, isize); 4] = [(-1, -1), (1, -1), (1, 1), (-1, 1)];
+        // the centre is the block with 3 neighbours, the T points away from the 4th side
+        for cy in 0..4 {
+            for cx in 0..4 {
+                let empty_sides = SIDES
+                    .iter()
+                    .filter(|(dx, dy)| !filled(cx + dx, cy + dy))
+                    .collect::<Vec<_>>();
+                if !filled(cx, cy) || empty_sides.len() != 1 {
+                    continue;
+                }
+                let (back_x, back_y) = empty_sides[0];
+                let corners = CORNERS
+                    .iter()
+                    .filter(|(dx, dy)| occupied(cx + dx, cy + dy))
+                    .collect::<Vec<_>>();
+                if corners.len() < 3 {
+                    return TSpin::None;
+                }
+                let front = corners
+                    .iter()
+                    .filter(|(dx, dy)| dx * back_x + dy * back_y < 0)
+                    .count();
+                return if front == 2 || (kick.0.abs() == 1 && kick.1.abs() == 2) {
+                    TSpin::Full
+                } else {
+                    TSpin::Mini
+                };
+            }
+        }
+        TSpin::None
+    }
+
+    /// Scores a locked piece that cleared `lines` rows, and keeps track of
+    /// combos and back-to-back chains.
+    fn score_lock(&mut self, lines: u32, tspin: TSpin, level: u32) {
+        let mut clear = Clear {
+            lines,
+            tspin,
+            combo: None,
+            back_to_back: false,
+        };
+        if lines > 0 {
+            self.combo = Some(self.combo.map_or(0, |combo| combo.saturating_add(1)));
+            clear.combo = self.combo;
+            clear.back_to_back = clear.is_difficult() && self.back_to_back;
+            self.back_to_back = clear.is_difficult();
+        } else {
+            self.combo = None;
+        }
+        self.update_score(self.scoring.lock(&clear, level));
+        self.last_clear = Some(clear);
+    }
+
+    /// Stamps the current piece into the map, scores it, and checks whether
+    /// where it locked ends the game.
+    fn make_permanent(&mut self) {
+        let tspin = self.detect_tspin();
+        let mut top_out = None;
+        if let Some(ref piece) = self.current_piece {
+            let hidden_rows = self.rules.hidden_rows as isize;
+            let mut hidden = 0;
+            let mut visible = 0;
+            for (shift_y, line) in piece.states[piece.current_state as usize]
+                .iter()
+                .enumerate()
+            {
+                for (shift_x, case) in line.iter().enumerate() {
+                    if *case == 0 {
+                        continue;
+                    }
+                    let x = piece.x + shift_x as isize;
+                    let y = piece.y + shift_y as isize;
+                    if y < 0
+                        || y as usize >= self.game_map.len()
+                        || x < 0
+                        || x as usize >= self.rules.width
+                    {
+                        // no room left to keep this block: the stack overflowed
+                        top_out = Some(GameOverReason::TopOut);
+                        continue;
+                    }
+                    self.game_map[y as usize][x as usize] = *case;
+                    if y < hidden_rows {
+                        hidden += 1;
+                    } else {
+                        visible += 1;
+                    }
+                }
+            }
+            if top_out.is_none() {
+                if visible == 0 && self.rules.lock_out {
+                    top_out = Some(GameOverReason::LockOut);
+                } else if hidden > 0 && self.rules.partial_lock_out {
+                    top_out = Some(GameOverReason::PartialLockOut);
+                }
+            }
+        }
+        // lines are scored at the level the piece locked at
+        let level = self.current_level;
+        let lines = self.check_lines();
+        self.score_lock(lines, tspin, level);
+        self.current_piece = None;
+        self.can_hold = true;
+        if let Some(reason) = top_out {
+            self.top_out(reason);
+        }
+        self.start_line_clear();
+        self.check_goal();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Inputs a test game is played with: each input in turn pressed,
+    /// then released a few frames later.
+    pub(crate) fn scripted(frame: u64) -> Option<InputEvent> {
+        let input = Input::ALL[(frame / 8 % Input::ALL.len() as u64) as usize];
+        match frame % 8 {
+            0 => Some(InputEvent::Press(input)),
+            5 => Some(InputEvent::Release(input)),
+            _ => None,
+        }
+    }
+
+    /// A game whose current piece is a `kind`, resting on the floor.
+    fn grounded(kind: PieceKind) -> Tetris {
+        let mut tetris = Tetris::new(1);
+        tetris.tick();
+        let piece = Tetrimino::new(kind, &*tetris.rotation, 10, 20);
+        tetris.lowest_y = piece.y;
+        tetris.current_piece = Some(piece);
+        while tetris.step(Input::SoftDrop) {}
+        assert!(tetris.is_grounded());
+        tetris
+    }
+
+    /// A game whose bottom rows are `rows`, 'X' standing for a block.
+    fn with_bottom(rows: &[&str]) -> Tetris {
+        let mut tetris = Tetris::new(1);
+        let height = tetris.game_map.len();
+        for (y, row) in rows.iter().enumerate() {
+            tetris.game_map[height - rows.len() + y] =
+                row.bytes().map(|case| (case == b'X') as u8).collect();
+        }
+        tetris
+    }
+
+    /// Puts a T at `(x, y)`, pointing down, as if it just rotated with `kick`.
+    fn t_spun(tetris: &mut Tetris, x: isize, y: isize, kick: Option<(isize, isize)>) {
+        let mut piece = Tetrimino::new(PieceKind::T, &*tetris.rotation, 10, 20);
+        piece.current_state = 2;
+        piece.x = x;
+        piece.y = y;
+        piece.last_kick = kick;
+        assert!(piece.test_current_position(&tetris.game_map));
+        tetris.current_piece = Some(piece);
+    }
+
+    #[test]
+    fn t_spins_count_corners() {
+        let full = ["...X.X....", "XXX...XXXX", "XXXX.XXXXX"];
+        let mut tetris = with_bottom(&full);
+        t_spun(&mut tetris, 3, 37, Some((0, 0)));
+        assert_eq!(tetris.detect_tspin(), TSpin::Full);
+        // it has to get there by rotating
+        t_spun(&mut tetris, 3, 37, None);
+        assert_eq!(tetris.detect_tspin(), TSpin::None);
+
+        // only one of the corners it points to is filled
+        let mini = ["...X.X....", "XXX...XXXX", "XXXX..XXXX"];
+        let mut tetris = with_bottom(&mini);
+        t_spun(&mut tetris, 3, 37, Some((-1, 0)));
+        assert_eq!(tetris.detect_tspin(), TSpin::Mini);
+        // unless it came through the far kick
+        t_spun(&mut tetris, 3, 37, Some((1, 2)));
+        assert_eq!(tetris.detect_tspin(), TSpin::Full);
+
+        let two_corners = ["...X......", "XXX...XXXX", "XXXX..XXXX"];
+        let mut tetris = with_bottom(&two_corners);
+        t_spun(&mut tetris, 3, 37, Some((0, 0)));
+        assert_eq!(tetris.detect_tspin(), TSpin::None);
+    }
+
+    #[test]
+    fn t_spins_are_scored() {
+        let mut tetris = with_bottom(&["...X.X....", "XXX...XXXX", "XXXX.XXXXX"]);
+        t_spun(&mut tetris, 3, 37, Some((0, 0)));
+        tetris.make_permanent();
+        let clear = tetris.last_clear.unwrap();
+        assert_eq!((clear.lines, clear.tspin), (2, TSpin::Full));
+        assert_eq!(tetris.score, 1200);
+    }
+
+    #[test]
+    fn spinning_on_the_floor_uses_up_resets() {
+        for kind in [PieceKind::T, PieceKind::J, PieceKind::L, PieceKind::S].iter() {
+            let mut tetris = grounded(*kind);
+            let mut rotations = 0;
+            let in_play = |tetris: &Tetris| matches!(tetris.phase, Phase::Falling | Phase::Locking);
+            while in_play(&tetris) && tetris.frame < 600 {
+                tetris.tick();
+                if !tetris.frame.is_multiple_of(10) || !in_play(&tetris) {
+                    continue;
+                }
+                let rotation = if rotations % 2 == 0 {
+                    Input::RotateCW
+                } else {
+                    Input::RotateCCW
+                };
+                if tetris.step(rotation) {
+                    rotations += 1;
+                }
+                assert!(tetris.lock_resets <= tetris.rules.move_reset_limit);
+            }
+            assert!(!in_play(&tetris), "{:?} never locked", kind);
+            assert_eq!(tetris.nb_pieces, 1);
+            assert!(rotations <= 16, "{:?} rotated {} times", kind, rotations);
+        }
+    }
+
+    #[test]
+    fn oversized_rules_are_rejected() {
+        let mut tetris = Tetris::new(1);
+        tetris.tick();
+        let saved = tetris.save();
+        let recorded = replay::Replay::new(&tetris).to_text();
+        let huge = [
+            ("hidden_rows 20", "hidden_rows 18446744073709551615"),
+            ("height 20", "height 9223372036854775807"),
+            ("width 10", "width 4611686018427387904"),
+            ("width 10", "width 101"),
+        ];
+        for (rule, value) in huge.iter() {
+            let text = saved.replacen(rule, value, 1);
+            assert!(Tetris::load(&text).is_err(), "save with {}", value);
+            let text = recorded.replacen(rule, value, 1);
+            assert!(
+                replay::Replay::parse(&text).is_err(),
+                "replay with {}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn chains_of_clears() {
+        let mut tetris = Tetris::new(1);
+        let mut lock = |lines| {
+            tetris.score_lock(lines, TSpin::None, 1);
+            let clear = tetris.last_clear.unwrap();
+            (clear.combo, clear.back_to_back, tetris.score)
+        };
+        assert_eq!(lock(4), (Some(0), false, 800));
+        assert_eq!(lock(4), (Some(1), true, 800 + 1200 + 50));
+        assert_eq!(lock(1), (Some(2), false, 2050 + 100 + 100));
+        assert_eq!(lock(0), (None, false, 2250));
+        // pieces clearing nothing don't break a back-to-back chain
+        assert_eq!(lock(4), (Some(0), false, 2250 + 800));
+        assert_eq!(lock(0), (None, false, 3050));
+        assert_eq!(lock(4), (Some(0), true, 3050 + 1200));
+    }
+
+    /// Stamps a T in its spawn orientation with its box at row `y`.
+    fn lock_t_at(tetris: &mut Tetris, y: isize) {
+        let mut piece = Tetrimino::new(PieceKind::T, &*tetris.rotation, 10, 20);
+        piece.y = y;
+        tetris.current_piece = Some(piece);
+        tetris.make_permanent();
+    }
+
+    #[test]
+    fn top_out_reasons() {
+        let mut tetris = Tetris::new(1);
+        for row in tetris.game_map[19..21].iter_mut() {
+            row.iter_mut().for_each(|case| *case = 1);
+        }
+        tetris.tick();
+        assert_eq!(tetris.game_over_reason(), Some(GameOverReason::BlockOut));
+
+        let mut tetris = Tetris::new(1);
+        lock_t_at(&mut tetris, 10);
+        assert_eq!(tetris.game_over_reason(), Some(GameOverReason::LockOut));
+
+        // half in the vanish zone: only ends the game if the rules say so
+        let mut tetris = Tetris::new(1);
+        lock_t_at(&mut tetris, 19);
+        assert_eq!(tetris.game_over_reason(), None);
+        let rules = Rules {
+            partial_lock_out: true,
+            ..Rules::default()
+        };
+        let mut tetris = Tetris::with_rules(rules, 1);
+        lock_t_at(&mut tetris, 19);
+        assert_eq!(
+            tetris.game_over_reason(),
+            Some(GameOverReason::PartialLockOut)
+        );
+
+        let mut tetris = Tetris::new(1);
+        tetris.game_map[0][0] = 1;
+        tetris.add_garbage(1);
+        tetris.raise_garbage();
+        assert_eq!(tetris.game_over_reason(), Some(GameOverReason::TopOut));
+        let mut tetris = Tetris::new(1);
+        lock_t_at(&mut tetris, -1);
+        assert_eq!(tetris.game_over_reason(), Some(GameOverReason::TopOut));
+    }
+
+    /// Where a T goes, tick after tick, with left held down from when it spawned.
+    fn shifting_left(rules: Rules, ticks: usize) -> Vec<isize> {
+        let mut tetris = Tetris::with_rules(rules, 1);
+        tetris.tick();
+        tetris.current_piece = Some(Tetrimino::new(PieceKind::T, &*tetris.rotation, 10, 20));
+        tetris.press(Input::MoveLeft);
+        (0..ticks)
+            .map(|_| {
+                tetris.tick();
+                tetris.current_piece.as_ref().unwrap().x
+            })
+            .collect()
+    }
+
+    #[test]
+    fn auto_shift_waits_das_then_repeats_every_arr() {
+        let rules = Rules {
+            das: 10,
+            arr: 2,
+            ..Rules::default()
+        };
+        assert_eq!(
+            shifting_left(rules, 14),
+            [2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 0, 0, 0]
+        );
+        let rules = Rules {
+            das: 4,
+            arr: 3,
+            ..Rules::default()
+        };
+        assert_eq!(shifting_left(rules, 10), [2, 2, 2, 1, 1, 1, 0, 0, 0, 0]);
+        // no repeat delay: straight to the wall
+        let rules = Rules {
+            das: 10,
+            arr: 0,
+            ..Rules::default()
+        };
+        assert_eq!(shifting_left(rules, 11)[8..], [2, 0, 0]);
+    }
+
+    #[test]
+    fn releasing_stops_auto_shift() {
+        let mut tetris = Tetris::new(1);
+        tetris.tick();
+        tetris.current_piece = Some(Tetrimino::new(PieceKind::T, &*tetris.rotation, 10, 20));
+        tetris.press(Input::MoveLeft);
+        tetris.press(Input::MoveRight);
+        tetris.release(Input::MoveRight);
+        // left takes over, charging again
+        for _ in 0..9 {
+            tetris.tick();
+        }
+        assert_eq!(tetris.current_piece.as_ref().unwrap().x, 3);
+        tetris.tick();
+        assert_eq!(tetris.current_piece.as_ref().unwrap().x, 2);
+        tetris.release(Input::MoveLeft);
+        for _ in 0..10 {
+            tetris.tick();
+        }
+        assert_eq!(tetris.current_piece.as_ref().unwrap().x, 2);
+    }
+
+    #[test]
+    fn soft_drop_speeds_gravity_up() {
+        let mut tetris = Tetris::new(1);
+        tetris.tick();
+        let gravity = tetris.gravity();
+        tetris.press(Input::SoftDrop);
+        assert_eq!(
+            tetris.gravity(),
+            gravity.times(Rules::default().soft_drop_factor)
+        );
+        tetris.release(Input::SoftDrop);
+        assert_eq!(tetris.gravity(), gravity);
+    }
+
+    /// Drops a T flat at the middle of `tetris`, then counts the ticks
+    /// until the next piece comes into play.
+    fn ticks_to_next_piece(tetris: &mut Tetris) -> u32 {
+        tetris.tick();
+        tetris.current_piece = Some(Tetrimino::new(PieceKind::T, &*tetris.rotation, 10, 20));
+        tetris.press(Input::HardDrop);
+        let mut ticks = 0;
+        while tetris.current_piece.is_none() {
+            tetris.tick();
+            ticks += 1;
+        }
+        ticks
+    }
+
+    #[test]
+    fn line_clear_and_entry_delays() {
+        let mut tetris = with_bottom(&["XXX...XXXX"]);
+        tetris.tick();
+        tetris.current_piece = Some(Tetrimino::new(PieceKind::T, &*tetris.rotation, 10, 20));
+        tetris.press(Input::HardDrop);
+        assert_eq!(tetris.phase(), Phase::LineClear);
+        assert_eq!(tetris.cleared_rows(), [39]);
+        for _ in 0..20 {
+            tetris.tick();
+        }
+        assert_eq!((tetris.phase(), tetris.delay_left()), (Phase::Entry, 6));
+        assert!(tetris.cleared_rows().is_empty());
+        for _ in 0..6 {
+            tetris.tick();
+        }
+        assert_eq!(tetris.phase(), Phase::Falling);
+        assert!(tetris.current_piece.is_some());
+
+        // no line cleared, only the entry delay
+        assert_eq!(ticks_to_next_piece(&mut Tetris::new(1)), 6);
+        let mut tetris = with_bottom(&["XXX...XXXX"]);
+        assert_eq!(ticks_to_next_piece(&mut tetris), 26);
+        // races don't wait
+        let rules = Rules {
+            mode: GameModeKind::Sprint,
+            ..Rules::default()
+        };
+        let mut tetris = Tetris::with_rules(rules, 1);
+        tetris.game_map[39] = with_bottom(&["XXX...XXXX"]).game_map[39].clone();
+        assert_eq!(ticks_to_next_piece(&mut tetris), 1);
+        assert_eq!(tetris.nb_lines, 1);
+    }
+
+    #[test]
+    fn rotations_and_hold_wait_for_the_next_piece() {
+        let mut tetris = Tetris::new(1);
+        tetris.tick();
+        tetris.press(Input::HardDrop);
+        assert!(tetris.current_piece.is_none());
+        let coming = tetris.next_piece.as_ref().unwrap().kind;
+        tetris.press(Input::RotateCW);
+        tetris.release(Input::RotateCW);
+        for _ in 0..6 {
+            tetris.tick();
+        }
+        let piece = tetris.current_piece.as_ref().unwrap();
+        assert_eq!((piece.kind, piece.current_state), (coming, 1));
+
+        tetris.press(Input::HardDrop);
+        let held = tetris.next_piece.as_ref().unwrap().kind;
+        tetris.press(Input::Hold);
+        for _ in 0..6 {
+            tetris.tick();
+        }
+        assert_eq!(tetris.hold_piece.as_ref().unwrap().kind, held);
+        assert!(!tetris.can_hold);
+        // the rotation was used up by the piece it came before
+        assert_eq!(tetris.current_piece.as_ref().unwrap().current_state, 0);
+    }
+
+    /// Rows of `game_map` with garbage in them.
+    fn garbage_rows(tetris: &Tetris) -> usize {
+        let rows = tetris.game_map.iter();
+        rows.filter(|line| line.contains(&GARBAGE)).count()
+    }
+
+    #[test]
+    fn garbage_rises_with_one_hole_a_row() {
+        let mut tetris = Tetris::new(1);
+        tetris.game_map[39][0] = 1;
+        tetris.add_garbage(5);
+        tetris.raise_garbage();
+        assert_eq!(tetris.game_map[34][0], 1);
+        assert_eq!(garbage_rows(&tetris), 5);
+        for line in &tetris.game_map[35..] {
+            assert_eq!(line.iter().filter(|case| **case == 0).count(), 1);
+            assert_eq!(line.iter().filter(|case| **case == GARBAGE).count(), 9);
+        }
+        assert!(!tetris.is_game_over());
+    }
+
+    #[test]
+    fn cheese_is_refilled_until_the_goal() {
+        let rules = Rules {
+            mode: GameModeKind::Cheese,
+            ..Rules::default()
+        };
+        let mut tetris = Tetris::with_rules(rules, 1);
+        assert_eq!(garbage_rows(&tetris), CHEESE_HEIGHT as usize);
+        let mut left = Vec::new();
+        while tetris.garbage_cleared < mode::CHEESE_LINES {
+            assert!(!tetris.is_game_over());
+            tetris.cleared_rows = vec![38, 39];
+            tetris.collapse_rows();
+            tetris.start_entry();
+            left.push(garbage_rows(&tetris));
+            tetris.tick();
+        }
+        // back up to the full height while there is garbage to come
+        assert_eq!(left, [10, 10, 10, 10, 8, 6, 4, 2, 0]);
+        assert_eq!(tetris.stat(Stat::GarbageLeft), "Left:0");
+        assert_eq!(tetris.game_over_reason(), Some(GameOverReason::GoalReached));
+    }
+}
@@ -0,0 +1,589 @@
+use super::gravity::{GravityCurve, GravityKind};
+use super::randomizer::RandomizerKind;
+use super::rotation::RotationKind;
+use super::scoring::ScoringKind;
+use super::{GameOverReason, LockReset, Rules, FRAME_RATE, LEVEL_LINES, MAX_LEVEL};
+use std::fmt::Debug;
+
+/// Lines a Marathon lasts.
+pub const MARATHON_LINES: u32 = 150;
+/// Lines a Sprint is raced over.
+pub const SPRINT_LINES: u32 = 40;
+/// Ticks an Ultra lasts: two minutes.
+pub const ULTRA_TICKS: u64 = 2 * 60 * FRAME_RATE as u64;
+/// Garbage lines a cheese race digs through.
+pub const CHEESE_LINES: u32 = 18;
+/// Rows of garbage kept in the playfield during a cheese race, while there
+/// are some left to come.
+pub const CHEESE_HEIGHT: u32 = 10;
+
+/// How long the game waits between two pieces, in ticks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Delays {
+    /// Cleared rows stay on show this long before the stack above comes down.
+    pub line_clear: u32,
+    /// Entry delay, or ARE: the wait before the next piece comes into play.
+    pub entry: u32,
+}
+
+/// Delays of the guideline games.
+const GUIDELINE_DELAYS: Delays = Delays {
+    line_clear: 20,
+    entry: 6,
+};
+/// Delays of NES Tetris, taking the entry delay at its shortest.
+const NES_DELAYS: Delays = Delays {
+    line_clear: 18,
+    entry: 10,
+};
+/// Races are played without waiting between pieces.
+const RACE_DELAYS: Delays = Delays {
+    line_clear: 0,
+    entry: 0,
+};
+
+/// Something shown about the game while it is played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stat {
+    Score,
+    Lines,
+    Level,
+    /// Time played so far.
+    Time,
+    /// Lines still to clear to reach the goal.
+    LinesLeft,
+    /// Time left before the game ends.
+    TimeLeft,
+    /// Garbage lines still to clear to reach the goal.
+    GarbageLeft,
+}
+
+/// Time as minutes, seconds and hundredths, from a number of ticks.
+pub fn format_time(ticks: u64) -> String {
+    let hundredths = ticks * 100 / FRAME_RATE as u64;
+    format!(
+        "{}:{:02}.{:02}",
+        hundredths / 6000,
+        hundredths / 100 % 60,
+        hundredths % 100
+    )
+}
+
+/// Defines what the player is after: when the game ends, how it speeds
+/// up, and what is worth showing about it.
+pub trait GameMode: Debug {
+    /// The rules the mode is played with, from those picked by the player:
+    /// modes with rules of their own override them.
+    fn rules(&self, rules: Rules) -> Rules;
+
+    /// Lowest and highest level a game can start at.
+    fn start_levels(&self) -> (u32, u32);
+
+    /// Lines to clear to finish the game, if that is its goal.
+    fn goal_lines(&self) -> Option<u32>;
+
+    /// Garbage lines to clear to finish the game, if it is a cheese race:
+    /// the playfield starts with garbage, refilled up to `CHEESE_HEIGHT` rows.
+    fn cheese_lines(&self) -> Option<u32>;
+
+    /// Ticks the game lasts, if it is timed.
+    fn time_limit(&self) -> Option<u64>;
+
+    /// Level after clearing `lines` in a game started at `start_level`.
+    fn level(&self, start_level: u32, lines: u32) -> u32;
+
+    /// Gravity curve the mode always plays with, whatever the rules pick:
+    /// one of `GravityKind`'s, or its own.
+    fn gravity(&self) -> Option<Box<dyn GravityCurve>>;
+
+    fn delays(&self) -> Delays;
+
+    /// Whether topping out clears the playfield instead of ending the game.
+    fn survives_top_out(&self) -> bool;
+
+    /// Stats shown while playing, most important first.
+    fn stats(&self) -> [Stat; 3];
+
+    /// Whether a game that ended for `reason` goes on the leaderboard.
+    fn ranks(&self, reason: GameOverReason) -> bool;
+
+    /// Whether the leaderboard puts the fastest games first, rather than
+    /// the best scores.
+    fn ranked_by_time(&self) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum GameModeKind {
+    /// Levels go up as lines are cleared, until 150 lines.
+    #[default]
+    Marathon,
+    /// 40 lines, as fast as possible.
+    Sprint,
+    /// As many points as possible in two minutes.
+    Ultra,
+    /// Endless, and topping out only clears the playfield.
+    Zen,
+    /// NES Tetris, its rules and its speed, from level 0.
+    Classic,
+    /// 18 lines of garbage to dig through, as fast as possible.
+    Cheese,
+}
+
+impl GameModeKind {
+    pub const ALL: [GameModeKind; 6] = [
+        GameModeKind::Marathon,
+        GameModeKind::Sprint,
+        GameModeKind::Ultra,
+        GameModeKind::Zen,
+        GameModeKind::Classic,
+        GameModeKind::Cheese,
+    ];
+
+    pub fn create(self) -> Box<dyn GameMode> {
+        match self {
+            GameModeKind::Marathon => Box::new(Marathon),
+            GameModeKind::Sprint => Box::new(Sprint),
+            GameModeKind::Ultra => Box::new(Ultra),
+            GameModeKind::Zen => Box::new(Zen),
+            GameModeKind::Classic => Box::new(Classic),
+            GameModeKind::Cheese => Box::new(Cheese),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Marathon;
+
+impl GameMode for Marathon {
+    fn rules(&self, rules: Rules) -> Rules {
+        rules
+    }
+
+    fn start_levels(&self) -> (u32, u32) {
+        (1, MAX_LEVEL)
+    }
+
+    fn goal_lines(&self) -> Option<u32> {
+        Some(MARATHON_LINES)
+    }
+
+    fn cheese_lines(&self) -> Option<u32> {
+        None
+    }
+
+    fn time_limit(&self) -> Option<u64> {
+        None
+    }
+
+    /// A level more every time `LEVEL_LINES` is passed.
+    fn level(&self, start_level: u32, lines: u32) -> u32 {
+        let passed = LEVEL_LINES.iter().filter(|needed| lines > **needed).count() as u32;
+        start_level.max(1 + passed)
+    }
+
+    fn gravity(&self) -> Option<Box<dyn GravityCurve>> {
+        None
+    }
+
+    fn delays(&self) -> Delays {
+        GUIDELINE_DELAYS
+    }
+
+    fn survives_top_out(&self) -> bool {
+        false
+    }
+
+    fn stats(&self) -> [Stat; 3] {
+        [Stat::Score, Stat::Lines, Stat::Level]
+    }
+
+    fn ranks(&self, _reason: GameOverReason) -> bool {
+        true
+    }
+
+    fn ranked_by_time(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug)]
+pub struct Sprint;
+
+impl GameMode for Sprint {
+    fn rules(&self, rules: Rules) -> Rules {
+        rules
+    }
+
+    fn start_levels(&self) -> (u32, u32) {
+        (1, MAX_LEVEL)
+    }
+
+    fn goal_lines(&self) -> Option<u32> {
+        Some(SPRINT_LINES)
+    }
+
+    fn cheese_lines(&self) -> Option<u32> {
+        None
+    }
+
+    fn time_limit(&self) -> Option<u64> {
+        None
+    }
+
+    fn level(&self, start_level: u32, _lines: u32) -> u32 {
+        start_level
+    }
+
+    fn gravity(&self) -> Option<Box<dyn GravityCurve>> {
+        None
+    }
+
+    fn delays(&self) -> Delays {
+        RACE_DELAYS
+    }
+
+    fn survives_top_out(&self) -> bool {
+        false
+    }
+
+    fn stats(&self) -> [Stat; 3] {
+        [Stat::Time, Stat::LinesLeft, Stat::Score]
+    }
+
+    /// Only finished races count.
+    fn ranks(&self, reason: GameOverReason) -> bool {
+        reason == GameOverReason::GoalReached
+    }
+
+    fn ranked_by_time(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug)]
+pub struct Ultra;
+
+impl GameMode for Ultra {
+    fn rules(&self, rules: Rules) -> Rules {
+        rules
+    }
+
+    fn start_levels(&self) -> (u32, u32) {
+        (1, MAX_LEVEL)
+    }
+
+    fn goal_lines(&self) -> Option<u32> {
+        None
+    }
+
+    fn cheese_lines(&self) -> Option<u32> {
+        None
+    }
+
+    fn time_limit(&self) -> Option<u64> {
+        Some(ULTRA_TICKS)
+    }
+
+    fn level(&self, start_level: u32, _lines: u32) -> u32 {
+        start_level
+    }
+
+    fn gravity(&self) -> Option<Box<dyn GravityCurve>> {
+        None
+    }
+
+    fn delays(&self) -> Delays {
+        RACE_DELAYS
+    }
+
+    fn survives_top_out(&self) -> bool {
+        false
+    }
+
+    fn stats(&self) -> [Stat; 3] {
+        [Stat::TimeLeft, Stat::Score, Stat::Lines]
+    }
+
+    fn ranks(&self, _reason: GameOverReason) -> bool {
+        true
+    }
+
+    fn ranked_by_time(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug)]
+pub struct Zen;
+
+impl GameMode for Zen {
+    fn rules(&self, rules: Rules) -> Rules {
+        rules
+    }
+
+    fn start_levels(&self) -> (u32, u32) {
+        (1, MAX_LEVEL)
+    }
+
+    fn goal_lines(&self) -> Option<u32> {
+        None
+    }
+
+    fn cheese_lines(&self) -> Option<u32> {
+        None
+    }
+
+    fn time_limit(&self) -> Option<u64> {
+        None
+    }
+
+    fn level(&self, start_level: u32, _lines: u32) -> u32 {
+        start_level
+    }
+
+    fn gravity(&self) -> Option<Box<dyn GravityCurve>> {
+        None
+    }
+
+    fn delays(&self) -> Delays {
+        GUIDELINE_DELAYS
+    }
+
+    fn survives_top_out(&self) -> bool {
+        true
+    }
+
+    fn stats(&self) -> [Stat; 3] {
+        [Stat::Score, Stat::Lines, Stat::Time]
+    }
+
+    fn ranks(&self, _reason: GameOverReason) -> bool {
+        true
+    }
+
+    fn ranked_by_time(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug)]
+pub struct Classic;
+
+/// Highest level NES Tetris lets a game start at.
+const CLASSIC_MAX_START_LEVEL: u32 = 19;
+
+impl GameMode for Classic {
+    /// NES pieces, rotation and scoring, without hard drop, hold or ghost,
+    /// and a DAS of 16 then 6 frames.
+    fn rules(&self, rules: Rules) -> Rules {
+        Rules {
+            randomizer: RandomizerKind::Nes,
+            rotation: RotationKind::Nrs,
+            scoring: ScoringKind::Nes,
+            gravity: GravityKind::Nes,
+            hold: false,
+            hard_drop: false,
+            ghost: false,
+            lock_delay: 0,
+            lock_reset: LockReset::Step,
+            das: 16,
+            arr: 6,
+            das_cut: 0,
+            ..rules
+        }
+    }
+
+    fn start_levels(&self) -> (u32, u32) {
+        (0, CLASSIC_MAX_START_LEVEL)
+    }
+
+    fn goal_lines(&self) -> Option<u32> {
+        None
+    }
+
+    fn cheese_lines(&self) -> Option<u32> {
+        None
+    }
+
+    fn time_limit(&self) -> Option<u64> {
+        None
+    }
+
+    /// The first level up comes after `10 * (start_level + 1)` lines, or
+    /// `max(100, 10 * start_level - 50)` if that is less, then every 10 lines.
+    fn level(&self, start_level: u32, lines: u32) -> u32 {
+        let first = (start_level * 10 + 10).min((start_level * 10).saturating_sub(50).max(100));
+        if lines < first {
+            start_level
+        } else {
+            start_level + 1 + (lines - first) / 10
+        }
+    }
+
+    fn gravity(&self) -> Option<Box<dyn GravityCurve>> {
+        Some(GravityKind::Nes.create())
+    }
+
+    fn delays(&self) -> Delays {
+        NES_DELAYS
+    }
+
+    fn survives_top_out(&self) -> bool {
+        false
+    }
+
+    fn stats(&self) -> [Stat; 3] {
+        [Stat::Score, Stat::Lines, Stat::Level]
+    }
+
+    fn ranks(&self, _reason: GameOverReason) -> bool {
+        true
+    }
+
+    fn ranked_by_time(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug)]
+pub struct Cheese;
+
+impl GameMode for Cheese {
+    fn rules(&self, rules: Rules) -> Rules {
+        rules
+    }
+
+    fn start_levels(&self) -> (u32, u32) {
+        (1, MAX_LEVEL)
+    }
+
+    fn goal_lines(&self) -> Option<u32> {
+        None
+    }
+
+    fn cheese_lines(&self) -> Option<u32> {
+        Some(CHEESE_LINES)
+    }
+
+    fn time_limit(&self) -> Option<u64> {
+        None
+    }
+
+    fn level(&self, start_level: u32, _lines: u32) -> u32 {
+        start_level
+    }
+
+    fn gravity(&self) -> Option<Box<dyn GravityCurve>> {
+        None
+    }
+
+    fn delays(&self) -> Delays {
+        RACE_DELAYS
+    }
+
+    fn survives_top_out(&self) -> bool {
+        false
+    }
+
+    fn stats(&self) -> [Stat; 3] {
+        [Stat::Time, Stat::GarbageLeft, Stat::Lines]
+    }
+
+    /// Only finished races count.
+    fn ranks(&self, reason: GameOverReason) -> bool {
+        reason == GameOverReason::GoalReached
+    }
+
+    fn ranked_by_time(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tetrimino::{PieceKind, Tetrimino};
+    use crate::engine::{Input, Tetris};
+
+    #[test]
+    fn classic_games_play_by_nes_rules() {
+        let rules = Rules {
+            mode: GameModeKind::Classic,
+            start_level: 0,
+            ..Rules::default()
+        };
+        let tetris = Tetris::with_rules(rules, 1);
+        assert_eq!(tetris.rules, Classic.rules(rules));
+        assert_eq!(tetris.rules.rotation, RotationKind::Nrs);
+        assert!(!tetris.rules.hold && !tetris.rules.hard_drop);
+        assert_eq!(tetris.rules.lock_delay, 0);
+        // saved and replayed games come back the same
+        let loaded = Tetris::load(&tetris.save()).unwrap();
+        assert_eq!(loaded.rules, tetris.rules);
+    }
+
+    #[test]
+    fn classic_levels_go_up_as_on_the_nes() {
+        // lines cleared on reaching the first levels up
+        let first = |start| (0..).find(|&lines| Classic.level(start, lines) > start);
+        assert_eq!(first(0), Some(10));
+        assert_eq!(first(5), Some(60));
+        assert_eq!(first(9), Some(100));
+        assert_eq!(first(15), Some(100));
+        assert_eq!(first(16), Some(110));
+        assert_eq!(first(18), Some(130));
+        assert_eq!(first(19), Some(140));
+        // then every 10 lines
+        assert_eq!(Classic.level(18, 139), 19);
+        assert_eq!(Classic.level(18, 140), 20);
+        assert_eq!(Classic.level(0, 235), 23);
+        assert_eq!(Classic.level(9, 99), 9);
+    }
+
+    fn game(mode: GameModeKind) -> Tetris {
+        let rules = Rules {
+            mode,
+            ..Rules::default()
+        };
+        let mut tetris = Tetris::with_rules(rules, 1);
+        tetris.tick();
+        tetris
+    }
+
+    #[test]
+    fn sprints_end_at_40_lines() {
+        let mut tetris = game(GameModeKind::Sprint);
+        tetris.nb_lines = SPRINT_LINES - 1;
+        tetris.tick();
+        assert!(!tetris.is_game_over());
+        // a T dropped flat into the gap clears the last line
+        let height = tetris.game_map.len();
+        tetris.game_map[height - 1] = vec![1, 1, 1, 0, 0, 0, 1, 1, 1, 1];
+        let piece = Tetrimino::new(PieceKind::T, &*tetris.rotation, 10, 20);
+        tetris.current_piece = Some(piece);
+        tetris.press(Input::HardDrop);
+        tetris.tick();
+        assert_eq!(tetris.nb_lines, SPRINT_LINES);
+        assert_eq!(tetris.game_over_reason(), Some(GameOverReason::GoalReached));
+        assert!(Sprint.ranks(GameOverReason::GoalReached));
+        assert!(!Sprint.ranks(GameOverReason::BlockOut));
+    }
+
+    #[test]
+    fn ultras_end_after_two_minutes() {
+        let mut tetris = game(GameModeKind::Ultra);
+        while tetris.frame < ULTRA_TICKS - 1 {
+            // keep the stack down, the clock is all that matters
+            for row in tetris.game_map.iter_mut() {
+                row.iter_mut().for_each(|case| *case = 0);
+            }
+            tetris.tick();
+        }
+        assert!(!tetris.is_game_over());
+        assert_eq!(tetris.stat(Stat::TimeLeft), "Time:0:00.01");
+        tetris.tick();
+        assert_eq!(tetris.game_over_reason(), Some(GameOverReason::TimeUp));
+        assert_eq!(tetris.stat(Stat::TimeLeft), "Time:0:00.00");
+        assert_eq!(format_time(ULTRA_TICKS), "2:00.00");
+    }
+}
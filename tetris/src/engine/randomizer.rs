@@ -0,0 +1,378 @@
+use std::fmt::Debug;
+
+/// Number of distinct tetriminos a randomizer picks from.
+pub const NB_PIECES: u8 = 7;
+
+/// Small xorshift64* generator: seedable and reproducible across platforms,
+/// so that two games with the same seed deal the same pieces.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // splitmix64 scrambling, so that close seeds still give unrelated sequences
+        // and a zero seed doesn't get xorshift stuck
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        Rng {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+
+    /// Internal state, as given back to `from_state`.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Resumes a generator from its internal state; None for a state it can't have.
+    pub fn from_state(state: u64) -> Option<Rng> {
+        if state == 0 {
+            None
+        } else {
+            Some(Rng { state })
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Returns a number in `0..max`.
+    pub fn below(&mut self, max: u8) -> u8 {
+        (self.next_u64() % max as u64) as u8
+    }
+}
+
+/// Decides which tetrimino comes next.
+pub trait Randomizer: Debug {
+    /// Returns the index of the next piece, in `0..NB_PIECES`.
+    fn next_piece(&mut self) -> u8;
+
+    /// Copies the randomizer along with its state, for game snapshots.
+    fn clone_box(&self) -> Box<dyn Randomizer>;
+
+    /// Numbers describing the randomizer's state, as read back by `set_state`.
+    fn state(&self) -> Vec<u64>;
+
+    /// Restores a state given by `state` on a randomizer of the same kind.
+    fn set_state(&mut self, state: &[u64]) -> Result<(), String>;
+}
+
+/// Splits a saved state into its generator and the pieces that follow,
+/// checking there are `len` pieces when given.
+fn read_state(state: &[u64], len: Option<usize>) -> Result<(Rng, Vec<u8>), String> {
+    let (rng, pieces) = match state.split_first() {
+        Some((rng, pieces)) => (Rng::from_state(*rng), pieces),
+        None => (None, state),
+    };
+    let rng = rng.ok_or("invalid randomizer state")?;
+    if matches!(len, Some(len) if len != pieces.len())
+        || pieces.iter().any(|piece| *piece >= NB_PIECES as u64)
+    {
+        return Err("invalid randomizer state".to_string());
+    }
+    Ok((rng, pieces.iter().map(|piece| *piece as u8).collect()))
+}
+
+impl Clone for Box<dyn Randomizer> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RandomizerKind {
+    /// Deals the 7 pieces in a shuffled bag, then refills it.
+    Bag7,
+    /// Same with a bag holding every piece twice.
+    Bag14,
+    /// Every piece is equally likely, repeats included.
+    Random,
+    /// Rerolls once when the same piece comes twice in a row.
+    #[default]
+    Reroll,
+    /// TGM: rerolls up to 4 times while the piece is in the last 4 dealt.
+    Tgm,
+    /// NES Tetris: rolls among the 7 pieces and a dummy one, then rerolls
+    /// once among the 7 on a repeat or the dummy.
+    Nes,
+}
+
+impl RandomizerKind {
+    pub const ALL: [RandomizerKind; 6] = [
+        RandomizerKind::Bag7,
+        RandomizerKind::Bag14,
+        RandomizerKind::Random,
+        RandomizerKind::Reroll,
+        RandomizerKind::Tgm,
+        RandomizerKind::Nes,
+    ];
+
+    pub fn create(self, seed: u64) -> Box<dyn Randomizer> {
+        match self {
+            RandomizerKind::Bag7 => Box::new(BagRandomizer::new(seed, 1)),
+            RandomizerKind::Bag14 => Box::new(BagRandomizer::new(seed, 2)),
+            RandomizerKind::Random => Box::new(RandomRandomizer::new(seed)),
+            RandomizerKind::Reroll => Box::new(RerollRandomizer::new(seed, false)),
+            RandomizerKind::Tgm => Box::new(HistoryRandomizer::new(seed, 4, 4)),
+            RandomizerKind::Nes => Box::new(RerollRandomizer::new(seed, true)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BagRandomizer {
+    rng: Rng,
+    copies: u8,
+    bag: Vec<u8>,
+}
+
+impl BagRandomizer {
+    pub fn new(seed: u64, copies: u8) -> BagRandomizer {
+        BagRandomizer {
+            rng: Rng::new(seed),
+            copies,
+            bag: Vec::new(),
+        }
+    }
+
+    fn refill(&mut self) {
+        for _ in 0..self.copies {
+            self.bag.extend(0..NB_PIECES);
+        }
+        // Fisher-Yates
+        for i in (1..self.bag.len()).rev() {
+            let j = (self.rng.next_u64() % (i as u64 + 1)) as usize;
+            self.bag.swap(i, j);
+        }
+    }
+}
+
+impl Randomizer for BagRandomizer {
+    fn next_piece(&mut self) -> u8 {
+        if self.bag.is_empty() {
+            self.refill();
+        }
+        self.bag.pop().unwrap()
+    }
+
+    fn state(&self) -> Vec<u64> {
+        let mut state = vec![self.rng.state()];
+        state.extend(self.bag.iter().map(|piece| *piece as u64));
+        state
+    }
+
+    fn set_state(&mut self, state: &[u64]) -> Result<(), String> {
+        let (rng, bag) = read_state(state, None)?;
+        if bag.len() > NB_PIECES as usize * self.copies as usize {
+            return Err("invalid randomizer state".to_string());
+        }
+        self.rng = rng;
+        self.bag = bag;
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RandomRandomizer {
+    rng: Rng,
+}
+
+impl RandomRandomizer {
+    pub fn new(seed: u64) -> RandomRandomizer {
+        RandomRandomizer {
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Randomizer for RandomRandomizer {
+    fn next_piece(&mut self) -> u8 {
+        self.rng.below(NB_PIECES)
+    }
+
+    fn state(&self) -> Vec<u64> {
+        vec![self.rng.state()]
+    }
+
+    fn set_state(&mut self, state: &[u64]) -> Result<(), String> {
+        self.rng = read_state(state, Some(0))?.0;
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RerollRandomizer {
+    rng: Rng,
+    prev: Option<u8>,
+    /// Whether the first roll can land on a dummy piece, which rerolls too.
+    dummy: bool,
+}
+
+impl RerollRandomizer {
+    pub fn new(seed: u64, dummy: bool) -> RerollRandomizer {
+        RerollRandomizer {
+            rng: Rng::new(seed),
+            prev: None,
+            dummy,
+        }
+    }
+}
+
+impl Randomizer for RerollRandomizer {
+    fn next_piece(&mut self) -> u8 {
+        let mut piece = self.rng.below(NB_PIECES + self.dummy as u8);
+        if piece == NB_PIECES || self.prev == Some(piece) {
+            piece = self.rng.below(NB_PIECES);
+        }
+        self.prev = Some(piece);
+        piece
+    }
+
+    fn state(&self) -> Vec<u64> {
+        let mut state = vec![self.rng.state()];
+        state.extend(self.prev.map(|piece| piece as u64));
+        state
+    }
+
+    fn set_state(&mut self, state: &[u64]) -> Result<(), String> {
+        let (rng, prev) = read_state(state, None)?;
+        if prev.len() > 1 {
+            return Err("invalid randomizer state".to_string());
+        }
+        self.rng = rng;
+        self.prev = prev.first().copied();
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoryRandomizer {
+    rng: Rng,
+    history: Vec<u8>,
+    rolls: u8,
+    first: bool,
+}
+
+/// S, Z and O pieces, which TGM never deals first.
+const TGM_NO_FIRST: [u8; 3] = [3, 4, 5];
+
+impl HistoryRandomizer {
+    pub fn new(seed: u64, history_len: usize, rolls: u8) -> HistoryRandomizer {
+        // TGM starts with a history full of Z pieces
+        HistoryRandomizer {
+            rng: Rng::new(seed),
+            history: vec![5; history_len],
+            rolls,
+            first: true,
+        }
+    }
+}
+
+impl Randomizer for HistoryRandomizer {
+    fn next_piece(&mut self) -> u8 {
+        let mut piece = self.rng.below(NB_PIECES);
+        if self.first {
+            while TGM_NO_FIRST.contains(&piece) {
+                piece = self.rng.below(NB_PIECES);
+            }
+            self.first = false;
+        } else {
+            for _ in 1..self.rolls {
+                if !self.history.contains(&piece) {
+                    break;
+                }
+                piece = self.rng.below(NB_PIECES);
+            }
+        }
+        self.history.remove(0);
+        self.history.push(piece);
+        piece
+    }
+
+    fn state(&self) -> Vec<u64> {
+        let mut state = vec![self.rng.state(), self.first as u64];
+        state.extend(self.history.iter().map(|piece| *piece as u64));
+        state
+    }
+
+    fn set_state(&mut self, state: &[u64]) -> Result<(), String> {
+        let (first, rest) = match state {
+            [rng, first, history @ ..] if *first <= 1 => {
+                (*first == 1, [&[*rng][..], history].concat())
+            }
+            _ => return Err("invalid randomizer state".to_string()),
+        };
+        let (rng, history) = read_state(&rest, Some(self.history.len()))?;
+        self.rng = rng;
+        self.first = first;
+        self.history = history;
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deal(randomizer: &mut dyn Randomizer, count: usize) -> Vec<u8> {
+        (0..count).map(|_| randomizer.next_piece()).collect()
+    }
+
+    #[test]
+    fn same_seed_same_pieces() {
+        for kind in RandomizerKind::ALL.iter() {
+            let pieces = deal(&mut *kind.create(42), 200);
+            assert_eq!(deal(&mut *kind.create(42), 200), pieces, "{:?}", kind);
+            assert_ne!(deal(&mut *kind.create(43), 200), pieces, "{:?}", kind);
+            assert!(pieces.iter().all(|piece| *piece < NB_PIECES));
+        }
+    }
+
+    #[test]
+    fn restored_state_deals_the_same_pieces() {
+        for kind in RandomizerKind::ALL.iter() {
+            let mut randomizer = kind.create(7);
+            deal(&mut *randomizer, 10);
+            let mut restored = kind.create(0);
+            restored.set_state(&randomizer.state()).unwrap();
+            let mut clone = randomizer.clone();
+            let pieces = deal(&mut *randomizer, 100);
+            assert_eq!(deal(&mut *restored, 100), pieces, "{:?}", kind);
+            assert_eq!(deal(&mut *clone, 100), pieces, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn bags_deal_every_piece() {
+        let mut randomizer = RandomizerKind::Bag7.create(1);
+        for _ in 0..10 {
+            let mut bag = deal(&mut *randomizer, NB_PIECES as usize);
+            bag.sort_unstable();
+            assert_eq!(bag, (0..NB_PIECES).collect::<Vec<_>>());
+        }
+    }
+}
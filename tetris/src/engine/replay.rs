@@ -0,0 +1,272 @@
+use super::{parse_variant, GameOverReason, Input, InputEvent, Rules, State, Tetris};
+use std::fmt::Write;
+
+/// First line of a replay; the number changes whenever the format or the
+/// game's timings do, as older replays would play differently.
+const HEADER: &str = "tetris-replay 6";
+
+/// Everything needed to play a game again: its seed, its rules, and the
+/// inputs pressed and released with the frame they happened on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub rules: Rules,
+    /// Sorted by frame; several inputs can share a frame.
+    pub inputs: Vec<(u64, InputEvent)>,
+    /// Frame the player gave up on, if they did.
+    pub quit: Option<u64>,
+}
+
+impl Replay {
+    /// Starts recording a game that has just been created.
+    pub fn new(tetris: &Tetris) -> Replay {
+        Replay {
+            seed: tetris.seed,
+            rules: tetris.rules,
+            inputs: Vec::new(),
+            quit: None,
+        }
+    }
+
+    /// Applies a key press or release to the game, and remembers it if it
+    /// could have had an effect: presses while running, releases until the
+    /// game is over.
+    pub fn handle(&mut self, tetris: &mut Tetris, event: InputEvent) -> bool {
+        let effective = match event {
+            InputEvent::Press(_) => tetris.state == State::Running,
+            InputEvent::Release(_) => !tetris.is_game_over(),
+        };
+        if effective {
+            self.inputs.push((tetris.frame, event));
+        }
+        tetris.handle(event)
+    }
+
+    /// Ends the game on the player's request, and remembers when.
+    pub fn quit(&mut self, tetris: &mut Tetris) {
+        if !tetris.is_game_over() {
+            self.quit = Some(tetris.frame);
+            tetris.end_game(GameOverReason::Quit);
+        }
+    }
+
+    /// Inputs recorded on `frame`, in the order they came.
+    pub fn inputs_at(&self, frame: u64) -> impl Iterator<Item = InputEvent> + '_ {
+        let start = self.inputs.partition_point(|(f, _)| *f < frame);
+        self.inputs[start..]
+            .iter()
+            .take_while(move |(f, _)| *f == frame)
+            .map(|(_, event)| *event)
+    }
+
+    /// Applies what was recorded on the frame `tetris` just reached.
+    pub fn feed(&self, tetris: &mut Tetris) {
+        for event in self.inputs_at(tetris.frame) {
+            tetris.handle(event);
+        }
+        if self.quit == Some(tetris.frame) {
+            tetris.end_game(GameOverReason::Quit);
+        }
+    }
+
+    /// Plays the whole replay at once, and returns the finished game. Games
+    /// that can't end on their own, as Zen, stop after the last input, if
+    /// the player didn't quit after it.
+    pub fn play(&self) -> Tetris {
+        let mut tetris = Tetris::with_rules(self.rules, self.seed);
+        let endless = tetris.mode().survives_top_out();
+        let last = self.inputs.last().map_or(0, |(frame, _)| *frame);
+        let last = last.max(self.quit.unwrap_or(0));
+        self.feed(&mut tetris);
+        while !tetris.is_game_over() {
+            if endless && tetris.frame >= last {
+                break;
+            }
+            tetris.tick();
+            self.feed(&mut tetris);
+        }
+        tetris
+    }
+
+    /// Reads a replay as written by `to_text`.
+    pub fn parse(text: &str) -> Result<Replay, String> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            Some((_, line)) if line.starts_with("tetris-replay ") => {
+                return Err(format!("unsupported version '{}'", line))
+            }
+            _ => return Err(format!("line 1: expected '{}'", HEADER)),
+        }
+        let mut seed = None;
+        let mut rules = Rules::default();
+        let mut inputs = Vec::new();
+        let mut quit = None;
+        for (nb, line) in lines {
+            let error = |message: String| format!("line {}: {}", nb + 1, message);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap_or_default();
+            let args = words.collect::<Vec<_>>();
+            let frame = |arg: &str| {
+                arg.parse::<u64>()
+                    .map_err(|_| error(format!("invalid frame '{}'", arg)))
+            };
+            match (name, &args[..]) {
+                ("seed", [value]) => {
+                    seed = Some(
+                        value
+                            .parse()
+                            .map_err(|_| error(format!("invalid seed '{}'", value)))?,
+                    )
+                }
+                ("press", [at, input]) | ("release", [at, input]) => {
+                    let at = frame(at)?;
+                    if matches!(inputs.last(), Some((last, _)) if *last > at) {
+                        return Err(error("inputs are not in order".to_string()));
+                    }
+                    let input = parse_variant(&Input::ALL, input)
+                        .ok_or_else(|| error(format!("unknown input '{}'", input)))?;
+                    inputs.push((
+                        at,
+                        match name {
+                            "press" => InputEvent::Press(input),
+                            _ => InputEvent::Release(input),
+                        },
+                    ));
+                }
+                ("quit", [at]) => quit = Some(frame(at)?),
+                (name, [value]) => {
+                    if !rules.set(name, value).map_err(error)? {
+                        return Err(error(format!("unknown rule '{}'", name)));
+                    }
+                }
+                _ => return Err(error(format!("can't read '{}'", line))),
+            }
+        }
+        rules.validate()?;
+        Ok(Replay {
+            seed: seed.ok_or("missing seed")?,
+            rules,
+            inputs,
+            quit,
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\nseed {}\n", HEADER, self.seed);
+        self.rules.write(&mut text);
+        for (frame, event) in &self.inputs {
+            let _ = match event {
+                InputEvent::Press(input) => writeln!(text, "press {} {:?}", frame, input),
+                InputEvent::Release(input) => writeln!(text, "release {} {:?}", frame, input),
+            };
+        }
+        if let Some(frame) = self.quit {
+            let _ = writeln!(text, "quit {}", frame);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::mode::GameModeKind;
+    use crate::engine::tests::scripted;
+
+    #[test]
+    fn replays_play_the_same_game() {
+        let mut tetris = Tetris::new(7);
+        let mut replay = Replay::new(&tetris);
+        while tetris.frame < 3000 && !tetris.is_game_over() {
+            if let Some(event) = scripted(tetris.frame) {
+                replay.handle(&mut tetris, event);
+            }
+            tetris.tick();
+        }
+        replay.quit(&mut tetris);
+        assert!(tetris.nb_pieces > 10);
+
+        let parsed = Replay::parse(&replay.to_text()).unwrap();
+        assert_eq!(parsed, replay);
+        let played = parsed.play();
+        assert_eq!(played.game_map, tetris.game_map);
+        assert_eq!(played.score, tetris.score);
+        assert_eq!(played.nb_lines, tetris.nb_lines);
+        assert_eq!(played.nb_pieces, tetris.nb_pieces);
+        assert_eq!(played.frame, tetris.frame);
+        assert_eq!(played.state, tetris.state);
+    }
+
+    #[test]
+    fn pauses_dont_change_the_game() {
+        let left = Input::MoveLeft;
+        for released_while_paused in [false, true].iter() {
+            let mut tetris = Tetris::new(3);
+            let mut replay = Replay::new(&tetris);
+            while tetris.frame < 200 {
+                match tetris.frame {
+                    5 => {
+                        replay.handle(&mut tetris, InputEvent::Press(left));
+                    }
+                    10 if !tetris.is_pause() => {
+                        tetris.toggle_pause();
+                        tetris.tick();
+                        if *released_while_paused {
+                            replay.handle(&mut tetris, InputEvent::Release(left));
+                        }
+                        tetris.toggle_pause();
+                    }
+                    40 => {
+                        replay.handle(&mut tetris, InputEvent::Release(left));
+                    }
+                    // locks the piece where auto shift took it
+                    100 => {
+                        replay.handle(&mut tetris, InputEvent::Press(Input::HardDrop));
+                    }
+                    _ => {}
+                }
+                tetris.tick();
+            }
+            replay.quit(&mut tetris);
+            let played = replay.play();
+            assert_eq!(played.game_map, tetris.game_map);
+        }
+    }
+
+    #[test]
+    fn endless_replays_stop_after_the_last_input() {
+        let mut tetris = Tetris::with_rules(
+            Rules {
+                mode: GameModeKind::Zen,
+                ..Rules::default()
+            },
+            2,
+        );
+        let mut replay = Replay::new(&tetris);
+        while tetris.frame < 500 {
+            if let Some(event) = scripted(tetris.frame) {
+                replay.handle(&mut tetris, event);
+            }
+            tetris.tick();
+        }
+        let played = replay.play();
+        assert!(!played.is_game_over());
+        assert_eq!(played.frame, replay.inputs.last().unwrap().0);
+    }
+
+    #[test]
+    fn broken_replays_are_rejected() {
+        let text = Replay::new(&Tetris::new(1)).to_text();
+        assert!(Replay::parse(&text).is_ok());
+        assert!(Replay::parse(&text.replace(HEADER, "tetris-replay 1")).is_err());
+        assert!(Replay::parse(&text.replace("seed 1\n", "")).is_err());
+        let unordered = format!("{}press 10 Hold\npress 5 Hold\n", text);
+        assert!(Replay::parse(&unordered).is_err());
+        assert!(Replay::parse(&format!("{}press 1 Jump\n", text)).is_err());
+    }
+}
@@ -0,0 +1,436 @@
+use super::tetrimino::{is_blocked, PieceKind, States, Tetrimino};
+use std::fmt::Debug;
+
+/// One rotation state of a piece inside its 4x4 box, 'X' marking a block.
+type Shape = [&'static str; 4];
+/// Kicks to try when rotating, as (x, y) offsets with y pointing down.
+pub type Kicks = &'static [(isize, isize)];
+
+/// Defines how pieces look in each orientation, where they spawn and how they kick.
+pub trait RotationSystem: Debug {
+    /// Rotation states of the piece, spawn orientation first, in clockwise order.
+    fn states(&self, kind: PieceKind) -> States;
+
+    /// Position of the piece's box when it spawns on a board `width` cells wide,
+    /// the row being relative to the first visible one: pieces spawn with their
+    /// lowest blocks on the top visible row, sticking out in the vanish zone.
+    fn spawn_position(&self, kind: PieceKind, width: usize) -> (isize, isize);
+
+    /// Offsets to try in turn when `piece` rotates into `new_state`.
+    fn kicks(
+        &self,
+        piece: &Tetrimino,
+        game_map: &[Vec<u8>],
+        new_state: usize,
+        clockwise: bool,
+    ) -> Kicks;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RotationKind {
+    /// Super Rotation System, from the guideline.
+    #[default]
+    Srs,
+    /// Arika Rotation System, from TGM.
+    Ars,
+    /// Nintendo Rotation System, from NES Tetris: no kicks at all.
+    Nrs,
+    /// The fixed list of horizontal shifts this game started with.
+    Legacy,
+}
+
+impl RotationKind {
+    pub const ALL: [RotationKind; 4] = [
+        RotationKind::Srs,
+        RotationKind::Ars,
+        RotationKind::Nrs,
+        RotationKind::Legacy,
+    ];
+
+    pub fn create(self) -> Box<dyn RotationSystem> {
+        match self {
+            RotationKind::Srs => Box::new(Srs),
+            RotationKind::Ars => Box::new(Ars),
+            RotationKind::Nrs => Box::new(Nrs),
+            RotationKind::Legacy => Box::new(Legacy),
+        }
+    }
+}
+
+fn build_states(kind: PieceKind, shapes: &[Shape]) -> States {
+    let value = kind as u8 + 1;
+    shapes
+        .iter()
+        .map(|shape| {
+            shape
+                .iter()
+                .map(|line| {
+                    line.bytes()
+                        .map(|c| if c == b'X' { value } else { 0 })
+                        .collect()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+const NO_KICK: Kicks = &[(0, 0)];
+
+#[derive(Debug)]
+pub struct Srs;
+
+/// SRS wall kicks for J, L, S, T and Z.
+/// Indexed by `from_state * 2`, plus one when rotating counter-clockwise.
+const SRS_JLSTZ_KICKS: [Kicks; 8] = [
+    &[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],  // 0->R
+    &[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],     // 0->L
+    &[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],    // R->2
+    &[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],    // R->0
+    &[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],     // 2->L
+    &[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],  // 2->R
+    &[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)], // L->0
+    &[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)], // L->2
+];
+
+/// SRS wall kicks for the I piece, same layout as `SRS_JLSTZ_KICKS`.
+const SRS_I_KICKS: [Kicks; 8] = [
+    &[(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)], // 0->R
+    &[(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)], // 0->L
+    &[(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)], // R->2
+    &[(0, 0), (2, 0), (-1, 0), (2, -1), (-1, 2)], // R->0
+    &[(0, 0), (2, 0), (-1, 0), (2, -1), (-1, 2)], // 2->L
+    &[(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)], // 2->R
+    &[(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)], // L->0
+    &[(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)], // L->2
+];
+
+impl RotationSystem for Srs {
+    fn states(&self, kind: PieceKind) -> States {
+        let shapes: &[Shape] = match kind {
+            PieceKind::I => &[
+                ["....", "XXXX", "....", "...."],
+                ["..X.", "..X.", "..X.", "..X."],
+                ["....", "....", "XXXX", "...."],
+                [".X..", ".X..", ".X..", ".X.."],
+            ],
+            PieceKind::J => &[
+                ["X...", "XXX.", "....", "...."],
+                [".XX.", ".X..", ".X..", "...."],
+                ["....", "XXX.", "..X.", "...."],
+                [".X..", ".X..", "XX..", "...."],
+            ],
+            PieceKind::L => &[
+                ["..X.", "XXX.", "....", "...."],
+                [".X..", ".X..", ".XX.", "...."],
+                ["....", "XXX.", "X...", "...."],
+                ["XX..", ".X..", ".X..", "...."],
+            ],
+            PieceKind::O => &[[".XX.", ".XX.", "....", "...."]; 4],
+            PieceKind::S => &[
+                [".XX.", "XX..", "....", "...."],
+                [".X..", ".XX.", "..X.", "...."],
+                ["....", ".XX.", "XX..", "...."],
+                ["X...", "XX..", ".X..", "...."],
+            ],
+            PieceKind::Z => &[
+                ["XX..", ".XX.", "....", "...."],
+                ["..X.", ".XX.", ".X..", "...."],
+                ["....", "XX..", ".XX.", "...."],
+                [".X..", "XX..", "X...", "...."],
+            ],
+            PieceKind::T => &[
+                [".X..", "XXX.", "....", "...."],
+                [".X..", ".XX.", ".X..", "...."],
+                ["....", "XXX.", ".X..", "...."],
+                [".X..", "XX..", ".X..", "...."],
+            ],
+        };
+        build_states(kind, shapes)
+    }
+
+    fn spawn_position(&self, kind: PieceKind, width: usize) -> (isize, isize) {
+        let box_width = match kind {
+            PieceKind::I | PieceKind::O => 4,
+            _ => 3,
+        };
+        ((width as isize - box_width) / 2, -1)
+    }
+
+    fn kicks(
+        &self,
+        piece: &Tetrimino,
+        _game_map: &[Vec<u8>],
+        _new_state: usize,
+        clockwise: bool,
+    ) -> Kicks {
+        let index = piece.current_state as usize * 2 + if clockwise { 0 } else { 1 };
+        match piece.kind {
+            PieceKind::I => SRS_I_KICKS[index],
+            PieceKind::O => NO_KICK,
+            _ => SRS_JLSTZ_KICKS[index],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Ars;
+
+const ARS_KICKS: Kicks = &[(0, 0), (1, 0), (-1, 0)];
+
+impl RotationSystem for Ars {
+    fn states(&self, kind: PieceKind) -> States {
+        let shapes: &[Shape] = match kind {
+            PieceKind::I => &[
+                ["....", "XXXX", "....", "...."],
+                ["..X.", "..X.", "..X.", "..X."],
+            ],
+            PieceKind::J => &[
+                ["....", "XXX.", "..X.", "...."],
+                [".X..", ".X..", "XX..", "...."],
+                ["....", "X...", "XXX.", "...."],
+                [".XX.", ".X..", ".X..", "...."],
+            ],
+            PieceKind::L => &[
+                ["....", "XXX.", "X...", "...."],
+                ["XX..", ".X..", ".X..", "...."],
+                ["....", "..X.", "XXX.", "...."],
+                [".X..", ".X..", ".XX.", "...."],
+            ],
+            PieceKind::O => &[["....", ".XX.", ".XX.", "...."]],
+            PieceKind::S => &[
+                ["....", ".XX.", "XX..", "...."],
+                ["X...", "XX..", ".X..", "...."],
+            ],
+            PieceKind::Z => &[
+                ["....", "XX..", ".XX.", "...."],
+                ["..X.", ".XX.", ".X..", "...."],
+            ],
+            PieceKind::T => &[
+                ["....", "XXX.", ".X..", "...."],
+                [".X..", "XX..", ".X..", "...."],
+                ["....", ".X..", "XXX.", "...."],
+                [".X..", ".XX.", ".X..", "...."],
+            ],
+        };
+        build_states(kind, shapes)
+    }
+
+    fn spawn_position(&self, kind: PieceKind, width: usize) -> (isize, isize) {
+        let box_width = match kind {
+            PieceKind::I | PieceKind::O => 4,
+            _ => 3,
+        };
+        let y = match kind {
+            PieceKind::I => -1,
+            _ => -2,
+        };
+        ((width as isize - box_width) / 2, y)
+    }
+
+    fn kicks(
+        &self,
+        piece: &Tetrimino,
+        game_map: &[Vec<u8>],
+        new_state: usize,
+        _clockwise: bool,
+    ) -> Kicks {
+        match piece.kind {
+            PieceKind::I | PieceKind::O => NO_KICK,
+            PieceKind::S | PieceKind::Z => ARS_KICKS,
+            PieceKind::J | PieceKind::L | PieceKind::T => {
+                // centre column rule: no kick when the first blocked cell of the
+                // rotated box, scanning rows from the top, is in the middle column
+                for (shift_y, line) in piece.states[new_state].iter().enumerate().take(3) {
+                    for (shift_x, case) in line.iter().enumerate().take(3) {
+                        let x = piece.x + shift_x as isize;
+                        let y = piece.y + shift_y as isize;
+                        if *case != 0 && is_blocked(game_map, x, y) {
+                            return if shift_x == 1 { NO_KICK } else { ARS_KICKS };
+                        }
+                    }
+                }
+                ARS_KICKS
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Nrs;
+
+impl RotationSystem for Nrs {
+    fn states(&self, kind: PieceKind) -> States {
+        let shapes: &[Shape] = match kind {
+            PieceKind::I => &[
+                ["....", "....", "XXXX", "...."],
+                ["..X.", "..X.", "..X.", "..X."],
+            ],
+            PieceKind::J => &[
+                ["....", "XXX.", "..X.", "...."],
+                [".X..", ".X..", "XX..", "...."],
+                ["X...", "XXX.", "....", "...."],
+                [".XX.", ".X..", ".X..", "...."],
+            ],
+            PieceKind::L => &[
+                ["....", "XXX.", "X...", "...."],
+                ["XX..", ".X..", ".X..", "...."],
+                ["..X.", "XXX.", "....", "...."],
+                [".X..", ".X..", ".XX.", "...."],
+            ],
+            PieceKind::O => &[["....", ".XX.", ".XX.", "...."]],
+            PieceKind::S => &[
+                ["....", ".XX.", "XX..", "...."],
+                [".X..", ".XX.", "..X.", "...."],
+            ],
+            PieceKind::Z => &[
+                ["....", "XX..", ".XX.", "...."],
+                ["..X.", ".XX.", ".X..", "...."],
+            ],
+            PieceKind::T => &[
+                ["....", "XXX.", ".X..", "...."],
+                [".X..", "XX..", ".X..", "...."],
+                [".X..", "XXX.", "....", "...."],
+                [".X..", ".XX.", ".X..", "...."],
+            ],
+        };
+        build_states(kind, shapes)
+    }
+
+    fn spawn_position(&self, kind: PieceKind, width: usize) -> (isize, isize) {
+        let x = match kind {
+            PieceKind::I | PieceKind::O => width as isize / 2 - 2,
+            _ => width as isize / 2 - 1,
+        };
+        (x, -2)
+    }
+
+    fn kicks(
+        &self,
+        _piece: &Tetrimino,
+        _game_map: &[Vec<u8>],
+        _new_state: usize,
+        _clockwise: bool,
+    ) -> Kicks {
+        NO_KICK
+    }
+}
+
+#[derive(Debug)]
+pub struct Legacy;
+
+const LEGACY_KICKS: Kicks = &[(0, 0), (-1, 0), (1, 0), (-2, 0), (2, 0), (-3, 0)];
+
+impl RotationSystem for Legacy {
+    fn states(&self, kind: PieceKind) -> States {
+        let shapes: &[Shape] = match kind {
+            PieceKind::I => &[
+                ["XXXX", "....", "....", "...."],
+                [".X..", ".X..", ".X..", ".X.."],
+            ],
+            PieceKind::J => &[
+                ["XXX.", "X...", "....", "...."],
+                ["XX..", ".X..", ".X..", "...."],
+                ["..X.", "XXX.", "....", "...."],
+                ["X...", "X...", "XX..", "...."],
+            ],
+            PieceKind::L => &[
+                ["XXX.", "..X.", "....", "...."],
+                [".X..", ".X..", "XX..", "...."],
+                ["X...", "XXX.", "....", "...."],
+                ["XX..", "X...", "X...", "...."],
+            ],
+            PieceKind::O => &[["XX..", "XX..", "....", "...."]],
+            PieceKind::S => &[
+                [".XX.", "XX..", "....", "...."],
+                [".X..", ".XX.", "..X.", "...."],
+            ],
+            PieceKind::Z => &[
+                ["XX..", ".XX.", "....", "...."],
+                ["..X.", ".XX.", ".X..", "...."],
+            ],
+            PieceKind::T => &[
+                ["XXX.", ".X..", "....", "...."],
+                [".X..", "XX..", ".X..", "...."],
+                [".X..", "XXX.", "....", "...."],
+                [".X..", ".XX.", ".X..", "...."],
+            ],
+        };
+        build_states(kind, shapes)
+    }
+
+    fn spawn_position(&self, kind: PieceKind, width: usize) -> (isize, isize) {
+        let x = match kind {
+            PieceKind::O => width as isize / 2,
+            _ => width as isize / 2 - 1,
+        };
+        let y = match kind {
+            PieceKind::I => 0,
+            _ => -1,
+        };
+        (x, y)
+    }
+
+    fn kicks(
+        &self,
+        _piece: &Tetrimino,
+        _game_map: &[Vec<u8>],
+        _new_state: usize,
+        _clockwise: bool,
+    ) -> Kicks {
+        LEGACY_KICKS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srs_kicks_undo_each_other() {
+        // rotating back the other way tries the opposite offsets
+        for kicks in [SRS_JLSTZ_KICKS, SRS_I_KICKS].iter() {
+            for state in 0..4 {
+                let clockwise = kicks[state * 2];
+                let back = kicks[(state + 1) % 4 * 2 + 1];
+                let undone = back.iter().map(|(x, y)| (-x, -y)).collect::<Vec<_>>();
+                assert_eq!(clockwise, &undone[..], "from state {}", state);
+            }
+        }
+    }
+
+    #[test]
+    fn srs_kicks_off_the_wall() {
+        let map = vec![vec![0; 10]; 40];
+        let mut piece = Tetrimino::new(PieceKind::I, &Srs, 10, 20);
+        assert!(piece.rotate(&map, false, &Srs));
+        while piece.change_position(&map, piece.x - 1, piece.y) {}
+        assert!(piece.rotate(&map, true, &Srs));
+        assert_eq!(piece.last_kick, Some((1, 0)));
+        assert_eq!((piece.x, piece.current_state), (0, 0));
+
+        let mut piece = Tetrimino::new(PieceKind::T, &Srs, 10, 20);
+        assert!(piece.rotate(&map, true, &Srs));
+        while piece.change_position(&map, piece.x - 1, piece.y) {}
+        assert!(piece.rotate(&map, false, &Srs));
+        assert_eq!(piece.last_kick, Some((1, 0)));
+    }
+
+    #[test]
+    fn srs_far_kick() {
+        // only where the T is and the slot it turns into are free, so that
+        // each kick before the last one is blocked
+        let mut map = vec![vec![1; 10]; 40];
+        let start = [(4, 34), (3, 35), (4, 35), (5, 35)];
+        let slot = [(5, 36), (4, 37), (5, 37), (5, 38)];
+        for (x, y) in start.iter().chain(slot.iter()) {
+            map[*y][*x] = 0;
+        }
+        let mut piece = Tetrimino::new(PieceKind::T, &Srs, 10, 20);
+        piece.x = 3;
+        piece.y = 34;
+        assert!(piece.test_current_position(&map));
+        assert!(piece.rotate(&map, false, &Srs));
+        assert_eq!(piece.last_kick, Some((1, 2)));
+        assert_eq!((piece.x, piece.y, piece.current_state), (4, 36, 3));
+    }
+}
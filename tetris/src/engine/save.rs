@@ -0,0 +1,346 @@
+use super::gravity::Gravity;
+use super::randomizer::Rng;
+use super::tetrimino::{PieceKind, Tetrimino};
+use super::{parse_variant, Phase, Rules, Tetris, GARBAGE};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// First line of a saved game; the number changes whenever the format does.
+const HEADER: &str = "tetris-save 7";
+
+fn write_piece(text: &mut String, name: &str, piece: Option<&Tetrimino>) {
+    match piece {
+        Some(piece) => {
+            let kick = match piece.last_kick {
+                Some((x, y)) => format!("{},{}", x, y),
+                None => "-".to_string(),
+            };
+            let _ = writeln!(
+                text,
+                "{} {:?} {} {} {} {}",
+                name, piece.kind, piece.x, piece.y, piece.current_state, kick
+            );
+        }
+        None => {
+            let _ = writeln!(text, "{} -", name);
+        }
+    }
+}
+
+/// Writes the kind of a piece waiting at its spawn position, as the next
+/// and held pieces do.
+fn write_kind(text: &mut String, name: &str, piece: Option<&Tetrimino>) {
+    match piece {
+        Some(piece) => {
+            let _ = writeln!(text, "{} {:?}", name, piece.kind);
+        }
+        None => {
+            let _ = writeln!(text, "{} -", name);
+        }
+    }
+}
+
+impl Tetris {
+    /// Writes down everything needed to resume the game later with `load`,
+    /// but for the keys held down: those are left out on purpose, a game is
+    /// resumed with every key released, so with auto shift to charge again
+    /// and no initial rotation or hold waiting for the next piece.
+    pub fn save(&self) -> String {
+        let mut text = format!("{}\nseed {}\n", HEADER, self.seed);
+        self.rules.write(&mut text);
+        let _ = writeln!(text, "frame {}", self.frame);
+        let _ = writeln!(text, "level {}", self.current_level);
+        let _ = writeln!(text, "score {}", self.score);
+        let _ = writeln!(text, "lines {}", self.nb_lines);
+        let _ = writeln!(text, "pieces {}", self.nb_pieces);
+        match self.combo {
+            Some(combo) => {
+                let _ = writeln!(text, "combo {}", combo);
+            }
+            None => text.push_str("combo -\n"),
+        }
+        let _ = writeln!(text, "back_to_back {}", self.back_to_back);
+        let _ = writeln!(text, "can_hold {}", self.can_hold);
+        let _ = writeln!(text, "lock_resets {}", self.lock_resets);
+        let _ = writeln!(text, "lowest_y {}", self.lowest_y);
+        let _ = writeln!(text, "phase {:?}", self.phase);
+        let _ = writeln!(text, "delay_timer {}", self.delay_timer);
+        if self.cleared_rows.is_empty() {
+            text.push_str("cleared_rows -\n");
+        } else {
+            let rows = self.cleared_rows.iter().map(usize::to_string);
+            let _ = writeln!(text, "cleared_rows {}", rows.collect::<Vec<_>>().join(" "));
+        }
+        let _ = writeln!(text, "fall_timer {}", self.fall_timer);
+        let _ = writeln!(text, "fall_progress {}", self.fall_progress);
+        let _ = writeln!(text, "garbage_cleared {}", self.garbage_cleared);
+        let _ = writeln!(text, "pending_garbage {}", self.pending_garbage);
+        let _ = writeln!(text, "cheese_left {}", self.cheese_left);
+        let _ = writeln!(text, "garbage_timer {}", self.garbage_timer);
+        let _ = writeln!(text, "garbage_rng {}", self.garbage_rng.state());
+        let state = self.randomizer.state();
+        let state = state.iter().map(u64::to_string).collect::<Vec<_>>();
+        let _ = writeln!(text, "randomizer_state {}", state.join(" "));
+        write_piece(&mut text, "current_piece", self.current_piece.as_ref());
+        write_kind(&mut text, "next_piece", self.next_piece.as_ref());
+        write_kind(&mut text, "hold_piece", self.hold_piece.as_ref());
+        for line in &self.game_map {
+            let line = line.iter().map(u8::to_string).collect::<String>();
+            let _ = writeln!(text, "row {}", line);
+        }
+        text
+    }
+
+    /// Resumes a game written by `save`. Files from another version, or
+    /// that don't describe a game that could have been played, are rejected.
+    pub fn load(text: &str) -> Result<Tetris, String> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            Some((_, line)) if line.starts_with("tetris-save ") => {
+                return Err(format!("unsupported version '{}'", line))
+            }
+            _ => return Err("not a saved game".to_string()),
+        }
+        let mut rules = Rules::default();
+        let mut values = HashMap::new();
+        let mut rows = Vec::new();
+        for (nb, line) in lines {
+            let error = |message: String| format!("line {}: {}", nb + 1, message);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = match line.find(' ') {
+                Some(index) => (&line[..index], line[index + 1..].trim()),
+                None => return Err(error(format!("can't read '{}'", line))),
+            };
+            if name == "row" {
+                rows.push(value);
+            } else if !rules.set(name, value).map_err(error)?
+                && values.insert(name, (nb + 1, value)).is_some()
+            {
+                return Err(error(format!("'{}' given twice", name)));
+            }
+        }
+        rules.validate()?;
+
+        // line number and value of the field called `name`
+        let field = |name: &str| {
+            values
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("missing '{}'", name))
+        };
+        fn parse<T: std::str::FromStr>(
+            (nb, value): (usize, &str),
+            name: &str,
+        ) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("line {}: invalid {} '{}'", nb, name, value))
+        }
+        let number = |name: &str| field(name).and_then(|value| parse::<u32>(value, name));
+        let flag = |name: &str| field(name).and_then(|value| parse::<bool>(value, name));
+
+        let seed = field("seed").and_then(|value| parse(value, "seed"))?;
+        let mut tetris = Tetris::with_rules(rules, seed);
+        tetris.frame = field("frame").and_then(|value| parse(value, "frame"))?;
+        tetris.current_level = number("level")?;
+        tetris.score = number("score")?;
+        tetris.nb_lines = number("lines")?;
+        // the level only ever follows from the lines cleared
+        if tetris.current_level != tetris.mode.level(rules.start_level, tetris.nb_lines) {
+            return Err(format!(
+                "invalid level {} after {} lines",
+                tetris.current_level, tetris.nb_lines
+            ));
+        }
+        tetris.nb_pieces = number("pieces")?;
+        tetris.combo = match field("combo")? {
+            (_, "-") => None,
+            value => Some(parse(value, "combo")?),
+        };
+        tetris.back_to_back = flag("back_to_back")?;
+        tetris.can_hold = flag("can_hold")?;
+        tetris.lock_resets = number("lock_resets")?;
+        tetris.lowest_y = field("lowest_y").and_then(|value| parse(value, "lowest_y"))?;
+        let (nb, phase) = field("phase")?;
+        tetris.phase = parse_variant(&Phase::ALL, phase)
+            .ok_or_else(|| format!("line {}: unknown phase '{}'", nb, phase))?;
+        tetris.delay_timer = number("delay_timer")?;
+        let (nb, cleared) = field("cleared_rows")?;
+        tetris.cleared_rows = cleared
+            .split_whitespace()
+            .filter(|row| *row != "-")
+            .map(|row| parse((nb, row), "cleared row"))
+            .collect::<Result<Vec<usize>, _>>()?;
+        tetris.fall_timer = number("fall_timer")?;
+        tetris.fall_progress =
+            field("fall_progress").and_then(|value| parse(value, "fall_progress"))?;
+        if tetris.fall_progress >= Gravity::CELL {
+            return Err(format!("invalid fall progress {}", tetris.fall_progress));
+        }
+        tetris.garbage_cleared = number("garbage_cleared")?;
+        tetris.pending_garbage = number("pending_garbage")?;
+        tetris.cheese_left = number("cheese_left")?;
+        tetris.garbage_timer = number("garbage_timer")?;
+        let (nb, state) = field("garbage_rng")?;
+        tetris.garbage_rng = parse((nb, state), "garbage_rng")
+            .ok()
+            .and_then(Rng::from_state)
+            .ok_or_else(|| format!("line {}: invalid garbage_rng '{}'", nb, state))?;
+
+        let (nb, state) = field("randomizer_state")?;
+        let state = state
+            .split_whitespace()
+            .map(|number| parse((nb, number), "randomizer state"))
+            .collect::<Result<Vec<u64>, _>>()?;
+        tetris
+            .randomizer
+            .set_state(&state)
+            .map_err(|err| format!("line {}: {}", nb, err))?;
+
+        if rows.len() != rules.hidden_rows + rules.height {
+            return Err(format!(
+                "{} rows for a {}x{} map with {} hidden rows",
+                rows.len(),
+                rules.width,
+                rules.height,
+                rules.hidden_rows
+            ));
+        }
+        for (y, row) in rows.iter().enumerate() {
+            let line = row
+                .chars()
+                .map(|case| {
+                    case.to_digit(10).filter(|case| {
+                        *case <= PieceKind::ALL.len() as u32 || *case == GARBAGE as u32
+                    })
+                })
+                .collect::<Option<Vec<_>>>();
+            match line {
+                Some(ref line) if line.len() == rules.width => {
+                    tetris.game_map[y] = line.iter().map(|case| *case as u8).collect();
+                }
+                _ => return Err(format!("invalid row '{}'", row)),
+            }
+        }
+
+        tetris.current_piece = tetris.read_piece(field("current_piece")?)?;
+        tetris.next_piece = tetris.read_kind(field("next_piece")?)?;
+        tetris.hold_piece = tetris.read_kind(field("hold_piece")?)?;
+        if tetris.next_piece.is_none() {
+            return Err("missing next piece".to_string());
+        }
+        if let Some(ref piece) = tetris.current_piece {
+            if !piece.test_current_position(&tetris.game_map) {
+                return Err("the current piece overlaps the map".to_string());
+            }
+        }
+        let in_play = matches!(tetris.phase, Phase::Falling | Phase::Locking);
+        if in_play != tetris.current_piece.is_some() {
+            return Err(format!("no current piece expected in {:?}", tetris.phase));
+        }
+        let map_rows = tetris.game_map.len();
+        if tetris.cleared_rows.iter().any(|y| *y >= map_rows)
+            || (tetris.phase == Phase::LineClear) == tetris.cleared_rows.is_empty()
+        {
+            return Err("the cleared rows don't match the map".to_string());
+        }
+        Ok(tetris)
+    }
+
+    /// Reads a piece written by `write_kind`, back at its spawn position.
+    fn read_kind(&self, (nb, value): (usize, &str)) -> Result<Option<Tetrimino>, String> {
+        if value == "-" {
+            return Ok(None);
+        }
+        let kind = parse_variant(&PieceKind::ALL, value)
+            .ok_or_else(|| format!("line {}: invalid piece '{}'", nb, value))?;
+        Ok(Some(Tetrimino::new(
+            kind,
+            &*self.rotation,
+            self.rules.width,
+            self.rules.hidden_rows,
+        )))
+    }
+
+    /// Reads a piece written by `write_piece`, placed as it was.
+    fn read_piece(&self, (nb, value): (usize, &str)) -> Result<Option<Tetrimino>, String> {
+        if value == "-" {
+            return Ok(None);
+        }
+        let error = || format!("line {}: invalid piece '{}'", nb, value);
+        let words = value.split_whitespace().collect::<Vec<_>>();
+        let (kind, x, y, state, kick) = match words[..] {
+            [kind, x, y, state, kick] => (kind, x, y, state, kick),
+            _ => return Err(error()),
+        };
+        let kind = parse_variant(&PieceKind::ALL, kind).ok_or_else(error)?;
+        let mut piece = Tetrimino::new(
+            kind,
+            &*self.rotation,
+            self.rules.width,
+            self.rules.hidden_rows,
+        );
+        piece.x = x.parse().map_err(|_| error())?;
+        piece.y = y.parse().map_err(|_| error())?;
+        piece.current_state = state.parse().map_err(|_| error())?;
+        if piece.current_state as usize >= piece.states.len() {
+            return Err(error());
+        }
+        piece.last_kick = match kick.split_once(',') {
+            Some((x, y)) => Some((
+                x.parse().map_err(|_| error())?,
+                y.parse().map_err(|_| error())?,
+            )),
+            None if kick == "-" => None,
+            None => return Err(error()),
+        };
+        Ok(Some(piece))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tests::scripted;
+
+    fn play(tetris: &mut Tetris, frames: u64) {
+        for _ in 0..frames {
+            if let Some(event) = scripted(tetris.frame) {
+                tetris.handle(event);
+            }
+            tetris.tick();
+        }
+    }
+
+    #[test]
+    fn loaded_games_play_on_the_same() {
+        let mut tetris = Tetris::new(5);
+        play(&mut tetris, 333);
+        assert!(tetris.current_piece.is_some());
+        let saved = tetris.save();
+        let mut loaded = Tetris::load(&saved).unwrap();
+        assert_eq!(loaded.save(), saved);
+
+        play(&mut tetris, 300);
+        play(&mut loaded, 300);
+        assert!(!tetris.is_game_over());
+        assert_eq!(loaded.game_map, tetris.game_map);
+        assert_eq!(loaded.score, tetris.score);
+        assert_eq!(loaded.save(), tetris.save());
+    }
+
+    #[test]
+    fn broken_saves_are_rejected() {
+        let saved = Tetris::new(1).save();
+        assert!(Tetris::load(&saved).is_ok());
+        assert!(Tetris::load(&saved.replace(HEADER, "tetris-save 1")).is_err());
+        assert!(Tetris::load(&saved.replacen("row ", "row 9", 1)).is_err());
+        assert!(Tetris::load("").is_err());
+        assert!(Tetris::load(&saved.replace("\nlevel 1\n", "\nlevel 7\n")).is_err());
+        assert!(Tetris::load(&saved.replacen("next_piece ", "next_piece X", 1)).is_err());
+    }
+}
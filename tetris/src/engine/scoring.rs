@@ -0,0 +1,211 @@
+use std::fmt::Debug;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TSpin {
+    None,
+    /// Only one of the two corners the T points to is filled.
+    Mini,
+    Full,
+}
+
+/// What a locked piece achieved, as seen by the scoring rules.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clear {
+    /// Number of rows cleared, from 0 to 4.
+    pub lines: u32,
+    pub tspin: TSpin,
+    /// Number of pieces in a row that cleared lines before this one, if this one did.
+    pub combo: Option<u32>,
+    /// Whether this is a difficult clear following another difficult one.
+    pub back_to_back: bool,
+}
+
+impl Clear {
+    /// Difficult clears are the ones that keep a back-to-back chain going.
+    pub fn is_difficult(&self) -> bool {
+        self.lines == 4 || (self.lines > 0 && self.tspin != TSpin::None)
+    }
+
+    /// Name of the action to show on screen, if it's worth showing.
+    pub fn label(&self) -> Option<String> {
+        let lines = match self.lines {
+            0 => "",
+            1 => "Single",
+            2 => "Double",
+            3 => "Triple",
+            _ => "Tetris",
+        };
+        let tspin = match self.tspin {
+            TSpin::None => "",
+            TSpin::Mini => "Mini T-Spin",
+            TSpin::Full => "T-Spin",
+        };
+        let label = format!("{} {}", tspin, lines).trim().to_string();
+        if label.is_empty() {
+            None
+        } else {
+            Some(label)
+        }
+    }
+}
+
+/// Decides how many points the player earns.
+pub trait Scoring: Debug {
+    /// Points for a piece that just locked.
+    fn lock(&self, clear: &Clear, level: u32) -> u32;
+
+    /// Points for `cells` rows of soft drop.
+    fn soft_drop(&self, cells: u32) -> u32;
+
+    /// Points for `cells` rows of hard drop.
+    fn hard_drop(&self, cells: u32) -> u32;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ScoringKind {
+    /// Modern guideline: back-to-back, combos and drop points.
+    #[default]
+    Guideline,
+    /// NES Tetris: line clears and soft drop only.
+    Nes,
+}
+
+impl ScoringKind {
+    pub const ALL: [ScoringKind; 2] = [ScoringKind::Guideline, ScoringKind::Nes];
+
+    pub fn create(self) -> Box<dyn Scoring> {
+        match self {
+            ScoringKind::Guideline => Box::new(GuidelineScoring),
+            ScoringKind::Nes => Box::new(NesScoring),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GuidelineScoring;
+
+const GUIDELINE_LINES: [u32; 5] = [0, 100, 300, 500, 800];
+const GUIDELINE_TSPIN: [u32; 4] = [400, 800, 1200, 1600];
+const GUIDELINE_MINI_TSPIN: [u32; 3] = [100, 200, 400];
+
+impl Scoring for GuidelineScoring {
+    fn lock(&self, clear: &Clear, level: u32) -> u32 {
+        let lines = clear.lines as usize;
+        let points = match clear.tspin {
+            TSpin::None => GUIDELINE_LINES[lines],
+            TSpin::Mini => GUIDELINE_MINI_TSPIN[lines.min(2)],
+            TSpin::Full => GUIDELINE_TSPIN[lines.min(3)],
+        };
+        let mut points = points.saturating_mul(level);
+        if clear.back_to_back {
+            points = points.saturating_add(points / 2);
+        }
+        if let Some(combo) = clear.combo {
+            points = points.saturating_add(50u32.saturating_mul(combo).saturating_mul(level));
+        }
+        points
+    }
+
+    fn soft_drop(&self, cells: u32) -> u32 {
+        cells
+    }
+
+    fn hard_drop(&self, cells: u32) -> u32 {
+        2 * cells
+    }
+}
+
+#[derive(Debug)]
+pub struct NesScoring;
+
+const NES_LINES: [u32; 5] = [0, 40, 100, 300, 1200];
+
+impl Scoring for NesScoring {
+    fn lock(&self, clear: &Clear, level: u32) -> u32 {
+        NES_LINES[clear.lines as usize].saturating_mul(level.saturating_add(1))
+    }
+
+    fn soft_drop(&self, cells: u32) -> u32 {
+        cells
+    }
+
+    fn hard_drop(&self, _cells: u32) -> u32 {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clear(lines: u32, tspin: TSpin) -> Clear {
+        Clear {
+            lines,
+            tspin,
+            combo: None,
+            back_to_back: false,
+        }
+    }
+
+    #[test]
+    fn guideline_line_clears() {
+        let scoring = GuidelineScoring;
+        let points = (0..=4)
+            .map(|lines| scoring.lock(&clear(lines, TSpin::None), 1))
+            .collect::<Vec<_>>();
+        assert_eq!(points, GUIDELINE_LINES);
+        assert_eq!(scoring.lock(&clear(4, TSpin::None), 3), 2400);
+        assert_eq!(scoring.lock(&clear(0, TSpin::Full), 1), 400);
+        assert_eq!(scoring.lock(&clear(2, TSpin::Full), 2), 2400);
+        assert_eq!(scoring.lock(&clear(0, TSpin::Mini), 1), 100);
+        assert_eq!(scoring.lock(&clear(1, TSpin::Mini), 1), 200);
+        assert_eq!((scoring.soft_drop(5), scoring.hard_drop(5)), (5, 10));
+    }
+
+    #[test]
+    fn guideline_back_to_back_and_combos() {
+        let scoring = GuidelineScoring;
+        let tetris = Clear {
+            back_to_back: true,
+            ..clear(4, TSpin::None)
+        };
+        assert_eq!(scoring.lock(&tetris, 1), 1200);
+        let triple = Clear {
+            back_to_back: true,
+            ..clear(3, TSpin::Full)
+        };
+        assert_eq!(scoring.lock(&triple, 2), 4800);
+        // the first clear of a chain has no combo bonus
+        let single = |combo| Clear {
+            combo: Some(combo),
+            ..clear(1, TSpin::None)
+        };
+        assert_eq!(scoring.lock(&single(0), 1), 100);
+        assert_eq!(scoring.lock(&single(3), 2), 200 + 300);
+    }
+
+    #[test]
+    fn difficult_clears() {
+        assert!(clear(4, TSpin::None).is_difficult());
+        assert!(clear(1, TSpin::Mini).is_difficult());
+        assert!(!clear(3, TSpin::None).is_difficult());
+        assert!(!clear(0, TSpin::Full).is_difficult());
+    }
+
+    #[test]
+    fn nes_scoring() {
+        let scoring = NesScoring;
+        let points = (0..=4)
+            .map(|lines| scoring.lock(&clear(lines, TSpin::None), 0))
+            .collect::<Vec<_>>();
+        assert_eq!(points, NES_LINES);
+        assert_eq!(scoring.lock(&clear(4, TSpin::None), 9), 12000);
+        let tetris = Clear {
+            back_to_back: true,
+            combo: Some(2),
+            ..clear(4, TSpin::None)
+        };
+        assert_eq!(scoring.lock(&tetris, 0), 1200);
+        assert_eq!((scoring.soft_drop(5), scoring.hard_drop(5)), (5, 0));
+    }
+}
@@ -0,0 +1,146 @@
+use super::rotation::RotationSystem;
+
+pub type Piece = Vec<Vec<u8>>;
+pub type States = Vec<Piece>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PieceKind {
+    I,
+    J,
+    L,
+    O,
+    S,
+    Z,
+    T,
+}
+
+impl PieceKind {
+    pub const ALL: [PieceKind; 7] = [
+        PieceKind::I,
+        PieceKind::J,
+        PieceKind::L,
+        PieceKind::O,
+        PieceKind::S,
+        PieceKind::Z,
+        PieceKind::T,
+    ];
+
+    /// Piece of the given index, as dealt by a `Randomizer`.
+    pub fn from_index(piece: u8) -> PieceKind {
+        match piece {
+            0 => PieceKind::I,
+            1 => PieceKind::J,
+            2 => PieceKind::L,
+            3 => PieceKind::O,
+            4 => PieceKind::S,
+            5 => PieceKind::Z,
+            6 => PieceKind::T,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Tetrimino {
+    pub kind: PieceKind,
+    pub states: States,
+    pub x: isize,
+    /// Row of the piece's box in the map, hidden rows included.
+    pub y: isize,
+    pub current_state: u8,
+    /// Kick offset used by the last rotation, if rotating was the last thing
+    /// this piece did.
+    pub last_kick: Option<(isize, isize)>,
+}
+
+impl Tetrimino {
+    /// Creates a tetrimino in its spawn orientation and position on a board
+    /// `width` cells wide, as defined by the rotation system. The spawn row is
+    /// relative to the first visible row, below `hidden_rows` rows of vanish zone.
+    pub fn new(
+        kind: PieceKind,
+        rotation: &dyn RotationSystem,
+        width: usize,
+        hidden_rows: usize,
+    ) -> Tetrimino {
+        let (x, y) = rotation.spawn_position(kind, width);
+        Tetrimino {
+            kind,
+            states: rotation.states(kind),
+            x,
+            y: y + hidden_rows as isize,
+            current_state: 0,
+            last_kick: None,
+        }
+    }
+
+    /// Rotates following the given rotation system, trying each of its kicks in turn.
+    /// Returns true if the piece could rotate.
+    pub fn rotate(
+        &mut self,
+        game_map: &[Vec<u8>],
+        clockwise: bool,
+        rotation: &dyn RotationSystem,
+    ) -> bool {
+        let nb_states = self.states.len() as u8;
+        let tmp_state = if clockwise {
+            (self.current_state + 1) % nb_states
+        } else {
+            (self.current_state + nb_states - 1) % nb_states
+        };
+        let kicks = rotation.kicks(self, game_map, tmp_state as usize, clockwise);
+        for (x, y) in kicks.iter() {
+            if self.test_position(game_map, tmp_state as usize, self.x + x, self.y + y) {
+                self.current_state = tmp_state;
+                self.x += x;
+                self.y += y;
+                self.last_kick = Some((*x, *y));
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn test_position(
+        &self,
+        game_map: &[Vec<u8>],
+        tmp_state: usize,
+        x: isize,
+        y: isize,
+    ) -> bool {
+        for shift_y in 0..4 {
+            for shift_x in 0..4 {
+                if self.states[tmp_state][shift_y as usize][shift_x as usize] != 0
+                    && is_blocked(game_map, x + shift_x, y + shift_y)
+                {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    pub fn test_current_position(&self, game_map: &[Vec<u8>]) -> bool {
+        self.test_position(game_map, self.current_state as usize, self.x, self.y)
+    }
+
+    pub fn change_position(&mut self, game_map: &[Vec<u8>], new_x: isize, new_y: isize) -> bool {
+        if self.test_position(game_map, self.current_state as usize, new_x, new_y) {
+            self.x = new_x;
+            self.y = new_y;
+            self.last_kick = None;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Whether the cell at (x, y) is taken, or outside of the map.
+pub fn is_blocked(game_map: &[Vec<u8>], x: isize, y: isize) -> bool {
+    y < 0
+        || y as usize >= game_map.len()
+        || x < 0
+        || x as usize >= game_map[y as usize].len()
+        || game_map[y as usize][x as usize] != 0
+}
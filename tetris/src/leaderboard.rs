@@ -0,0 +1,321 @@
+use crate::engine::mode::{format_time, GameModeKind};
+use crate::engine::{parse_variant, LEVEL_LINES};
+
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// First line of a score file; files without it are from before names
+/// were kept, and get migrated.
+const HEADER: &str = "tetris-scores 2";
+/// Entries kept for each game mode.
+pub const NB_HIGHSCORES: usize = 10;
+
+/// A finished game, as remembered on the leaderboard.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub mode: GameModeKind,
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,
+    /// How long the game lasted, in ticks; unknown for migrated scores.
+    pub frames: Option<u64>,
+    /// Day the game was played on, as `YYYY-MM-DD`; unknown for migrated scores.
+    pub date: Option<String>,
+}
+
+impl Entry {
+    /// Game duration as minutes, seconds and hundredths.
+    pub fn duration(&self) -> String {
+        self.frames.map_or("-".to_string(), format_time)
+    }
+
+    /// Whether the game ranks above `other`, of the same mode: it was
+    /// faster if the mode is a race, else it scored more.
+    fn beats(&self, other: &Entry) -> bool {
+        if self.mode.create().ranked_by_time() {
+            match (self.frames, other.frames) {
+                (Some(frames), Some(other)) => frames < other,
+                (frames, other) => frames.is_some() && other.is_none(),
+            }
+        } else {
+            self.score > other.score
+        }
+    }
+}
+
+/// The best games played, sorted by score, or by time for races, for each
+/// game mode.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Leaderboard {
+    /// Grouped by mode, in the order they were first played, best first.
+    entries: Vec<Entry>,
+}
+
+impl Leaderboard {
+    /// Entries of `mode`, best first.
+    pub fn entries(&self, mode: GameModeKind) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(move |entry| entry.mode == mode)
+    }
+
+    /// Rank `entry` would get, counting from 0, if that is good enough to
+    /// be kept.
+    pub fn rank(&self, entry: &Entry) -> Option<usize> {
+        Some(
+            self.entries(entry.mode)
+                .filter(|other| !entry.beats(other))
+                .count(),
+        )
+        .filter(|rank| *rank < NB_HIGHSCORES)
+    }
+
+    /// Adds a game after those of its mode at least as good, and drops the
+    /// worst of its mode if there are too many. Returns its rank, if kept.
+    pub fn insert(&mut self, entry: Entry) -> Option<usize> {
+        let rank = self.rank(&entry);
+        let mode = entry.mode;
+        let same_mode = |other: &Entry| other.mode == mode;
+        let index = self
+            .entries
+            .iter()
+            .position(|other| same_mode(other) && entry.beats(other))
+            .or_else(|| {
+                self.entries
+                    .iter()
+                    .rposition(same_mode)
+                    .map(|index| index + 1)
+            })
+            .unwrap_or(self.entries.len());
+        self.entries.insert(index, entry);
+        let mut kept = 0;
+        self.entries.retain(|entry| {
+            kept += (entry.mode == mode) as usize;
+            entry.mode != mode || kept <= NB_HIGHSCORES
+        });
+        rank
+    }
+
+    /// Reads a leaderboard as written by `to_text`, or the two lines of
+    /// scores and line counts older versions wrote.
+    pub fn parse(text: &str) -> Result<Leaderboard, String> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            Some((_, line)) if line.starts_with("tetris-scores ") => {
+                return Err(format!("unsupported version '{}'", line))
+            }
+            _ => return Leaderboard::parse_old(text),
+        }
+        let mut leaderboard = Leaderboard::default();
+        for (nb, line) in lines {
+            let error = || format!("line {}: can't read '{}'", nb + 1, line);
+            // the name goes last, it may have spaces or be empty: the end
+            // of the line is kept as it is
+            let line = line.trim_start();
+            if line.is_empty() {
+                continue;
+            }
+            let words = line.splitn(8, ' ').collect::<Vec<_>>();
+            let (mode, score, lines, level, frames, date, name) = match words[..] {
+                ["entry", mode, score, lines, level, frames, date, name] => {
+                    (mode, score, lines, level, frames, date, name)
+                }
+                _ => return Err(error()),
+            };
+            let mode = parse_variant(&GameModeKind::ALL, mode)
+                .ok_or_else(|| format!("line {}: unknown mode '{}'", nb + 1, mode))?;
+            leaderboard.insert(Entry {
+                name: name.to_string(),
+                mode,
+                score: score.parse().map_err(|_| error())?,
+                lines: lines.parse().map_err(|_| error())?,
+                level: level.parse().map_err(|_| error())?,
+                frames: match frames {
+                    "-" => None,
+                    frames => Some(frames.parse().map_err(|_| error())?),
+                },
+                date: Some(date.to_string()).filter(|date| date != "-"),
+            });
+        }
+        Ok(leaderboard)
+    }
+
+    /// Reads the scores and line counts of the old format, where each list
+    /// was kept on its own: they are paired up in order, the best they can be.
+    fn parse_old(text: &str) -> Result<Leaderboard, String> {
+        let mut numbers = text.lines().take(2).map(|line| {
+            line.split_whitespace()
+                .map(|number| number.parse::<u32>().ok())
+                .collect::<Option<Vec<_>>>()
+        });
+        let (scores, lines) = match (numbers.next(), numbers.next()) {
+            (Some(Some(scores)), Some(Some(lines))) => (scores, lines),
+            _ => return Err("not a score file".to_string()),
+        };
+        let mut leaderboard = Leaderboard::default();
+        for (index, &score) in scores.iter().enumerate() {
+            let lines = lines.get(index).copied().unwrap_or(0);
+            // old games all started at level 1, and couldn't go past the last one
+            let passed = LEVEL_LINES.iter().filter(|needed| lines > **needed).count();
+            let level = 1 + passed.min(LEVEL_LINES.len() - 1) as u32;
+            leaderboard.insert(Entry {
+                name: "-".to_string(),
+                mode: GameModeKind::Marathon,
+                score,
+                lines,
+                level,
+                frames: None,
+                date: None,
+            });
+        }
+        Ok(leaderboard)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n", HEADER);
+        for entry in &self.entries {
+            let frames = entry
+                .frames
+                .map_or("-".to_string(), |frames| frames.to_string());
+            let _ = writeln!(
+                text,
+                "entry {:?} {} {} {} {} {} {}",
+                entry.mode,
+                entry.score,
+                entry.lines,
+                entry.level,
+                frames,
+                entry.date.as_deref().unwrap_or("-"),
+                entry.name
+            );
+        }
+        text
+    }
+}
+
+/// Today's date, as `YYYY-MM-DD`, in UTC.
+pub fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    // days since the epoch to a civil date, from Howard Hinnant's algorithm
+    let days = (seconds / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, mode: GameModeKind, score: u32, frames: Option<u64>) -> Entry {
+        Entry {
+            name: name.to_string(),
+            mode,
+            score,
+            lines: 0,
+            level: 1,
+            frames,
+            date: Some("2024-01-02".to_string()),
+        }
+    }
+
+    #[test]
+    fn names_come_back_as_written() {
+        let mut leaderboard = Leaderboard::default();
+        for name in ["", "two words", "trailing ", "-"].iter() {
+            leaderboard.insert(entry(name, GameModeKind::Marathon, 100, None));
+        }
+        let text = leaderboard.to_text();
+        assert_eq!(Leaderboard::parse(&text), Ok(leaderboard));
+    }
+
+    #[test]
+    fn old_scores_become_marathon_entries() {
+        let leaderboard = Leaderboard::parse("500 300 900 40\n25 10 250\n").unwrap();
+        let entries = leaderboard
+            .entries(GameModeKind::Marathon)
+            .map(|entry| (entry.score, entry.lines, entry.level))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [(900, 250, 10), (500, 25, 2), (300, 10, 1), (40, 0, 1)]
+        );
+        let first = leaderboard.entries(GameModeKind::Marathon).next().unwrap();
+        assert_eq!(
+            (&first.name[..], first.frames, &first.date),
+            ("-", None, &None)
+        );
+        assert_eq!(Leaderboard::parse("\n\n"), Ok(Leaderboard::default()));
+
+        assert!(Leaderboard::parse("500 x\n25\n").is_err());
+        assert!(Leaderboard::parse("500\n").is_err());
+        assert!(Leaderboard::parse("tetris-scores 3\n").is_err());
+    }
+
+    #[test]
+    fn entries_are_ranked_within_their_mode() {
+        let mut leaderboard = Leaderboard::default();
+        let marathon = |score| entry("m", GameModeKind::Marathon, score, Some(100));
+        let sprint = |frames| entry("s", GameModeKind::Sprint, 0, frames);
+        assert_eq!(leaderboard.insert(marathon(300)), Some(0));
+        assert_eq!(leaderboard.insert(sprint(Some(5000))), Some(0));
+        assert_eq!(leaderboard.insert(marathon(500)), Some(0));
+        // ties go after
+        assert_eq!(leaderboard.insert(marathon(300)), Some(2));
+        // races by time, the unfinished last
+        assert_eq!(leaderboard.insert(sprint(None)), Some(1));
+        assert_eq!(leaderboard.insert(sprint(Some(4000))), Some(0));
+        assert_eq!(leaderboard.insert(sprint(Some(9000))), Some(2));
+
+        let scores = |leaderboard: &Leaderboard, mode| {
+            let entries = leaderboard.entries(mode);
+            entries
+                .map(|entry| (entry.score, entry.frames))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            scores(&leaderboard, GameModeKind::Marathon),
+            [(500, Some(100)), (300, Some(100)), (300, Some(100))]
+        );
+        assert_eq!(
+            scores(&leaderboard, GameModeKind::Sprint),
+            [(0, Some(4000)), (0, Some(5000)), (0, Some(9000)), (0, None)]
+        );
+        // each mode keeps its entries together
+        let modes = leaderboard.entries.iter().map(|entry| entry.mode);
+        assert_eq!(
+            modes.collect::<Vec<_>>(),
+            [&[GameModeKind::Marathon; 3][..], &[GameModeKind::Sprint; 4]].concat()
+        );
+    }
+
+    #[test]
+    fn only_the_best_are_kept() {
+        let mut leaderboard = Leaderboard::default();
+        leaderboard.insert(entry("z", GameModeKind::Zen, 1, None));
+        for score in 1..=NB_HIGHSCORES as u32 {
+            leaderboard.insert(entry("m", GameModeKind::Marathon, score * 10, None));
+        }
+        let worst = entry("m", GameModeKind::Marathon, 10, None);
+        assert_eq!(leaderboard.rank(&worst), None);
+        assert_eq!(leaderboard.insert(worst), None);
+        assert_eq!(
+            leaderboard.insert(entry("m", GameModeKind::Marathon, 15, None)),
+            Some(NB_HIGHSCORES - 1)
+        );
+        let marathon = leaderboard.entries(GameModeKind::Marathon);
+        let scores = marathon.map(|entry| entry.score).collect::<Vec<_>>();
+        assert_eq!(scores.len(), NB_HIGHSCORES);
+        assert_eq!(scores[NB_HIGHSCORES - 2..], [20, 15]);
+        assert_eq!(leaderboard.entries(GameModeKind::Zen).count(), 1);
+    }
+}
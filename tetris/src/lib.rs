@@ -0,0 +1,5 @@
+//! Headless Tetris rules: the game can be driven without any front end,
+//! the SDL binary being just one client of this library.
+
+pub mod engine;
+pub mod leaderboard;
@@ -1,7 +1,20 @@
+extern crate rand;
 extern crate sdl2;
 
-mod tetrimino;
-use crate::tetrimino::Tetrimino;
+mod controls;
+mod dirs;
+mod settings;
+
+use controls::{Action, Bindings, ControlsScreen, CONTROLS_KEY};
+use dirs::{assets_dir, migrate, Dirs};
+use settings::{Settings, SettingsScreen, Theme, SETTINGS_KEY};
+use tetris::engine::clock::{FixedStep, RealClock};
+use tetris::engine::history::History;
+use tetris::engine::mode::{format_time, GameModeKind};
+use tetris::engine::replay::Replay;
+use tetris::engine::tetrimino::Tetrimino;
+use tetris::engine::{InputEvent, Phase, Tetris, FRAME_RATE};
+use tetris::leaderboard::{today, Entry, Leaderboard};
 
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
@@ -10,143 +23,84 @@ use sdl2::rect::Rect;
 use sdl2::render::{Canvas, Texture, TextureCreator};
 use sdl2::video::{Window, WindowContext};
 
-use std::env::current_exe;
+use std::env::{self, args};
 use std::fs::File;
 use std::io::{self, Read, Write};
-use std::thread::sleep;
-use std::time::{Duration, SystemTime};
+use std::path::PathBuf;
 
-const WIDTH: u32 = 660;
-const HEIGHT: u32 = 660;
-const NUM_TEXTURES: usize = 7;
+/// Textures for the cell values of the map: the 7 pieces, then garbage.
+const NUM_TEXTURES: usize = 8;
 const TETRIS_HEIGHT: usize = 40;
 const HIGHSCORE_FILE: &str = "scores.txt";
-const LEVEL_TIMES: [u32; 10] = [1000, 850, 700, 600, 500, 400, 300, 250, 221, 190];
-const LEVEL_LINES: [u32; 10] = [20, 40, 60, 80, 100, 120, 140, 160, 180, 200];
-const NB_HIGHSCORES: usize = 5;
+const SAVE_FILE: &str = "save.txt";
+const CONTROLS_FILE: &str = "controls.txt";
+const SETTINGS_FILE: &str = "settings.txt";
+const PANEL_WIDTH: u32 = 240;
+const NEXT_BOX_Y: i32 = 240;
+/// Room kept under the playfield for the review timeline.
+const TIMELINE_HEIGHT: u32 = 30;
+const REVIEW_SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const MAX_NAME_LEN: usize = 12;
 
-enum State {
-    Running,
-    Pause,
-    GameOver,
-}
-struct Tetris {
-    score_file: String,
-    state: State,
-    game_map: Vec<Vec<u8>>,
-    current_level: u32,
-    score: u32,
-    nb_lines: u32,
-    current_piece: Option<Tetrimino>,
-    next_piece: Option<Tetrimino>,
+/// Where things go in the window, derived from the playfield dimensions.
+struct Layout {
+    /// Size of a cell, in pixels.
+    cell: u32,
+    grid_x: i32,
+    grid_y: i32,
+    grid_width: u32,
+    grid_height: u32,
+    /// Left of the side panel, where the game information is written.
+    panel_x: i32,
+    /// Left of the next and hold boxes.
+    box_x: i32,
+    /// Size of the next and hold boxes, borders included.
+    box_size: u32,
+    next_box_y: i32,
+    hold_box_y: i32,
+    /// Left of the pieces previewed after the next one, drawn at half size.
+    queue_x: i32,
+    timeline_y: i32,
+    width: u32,
+    height: u32,
 }
 
-impl Tetris {
-    fn new(score_file: &str) -> Tetris {
-        let mut game_map = Vec::new();
-        for _ in 0..16 {
-            game_map.push(vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
-        }
-        Tetris {
-            score_file: score_file.to_string(),
-            state: State::Running,
-            game_map,
-            current_level: 1,
-            score: 0,
-            nb_lines: 0,
-            current_piece: None,
-            next_piece: None,
-        }
-    }
-
-    fn is_pause(&self) -> bool {
-        match self.state {
-            State::Pause => true,
-            _ => false,
-        }
-    }
-
-    fn is_game_over(&self) -> bool {
-        match self.state {
-            State::GameOver => true,
-            _ => false,
-        }
-    }
-
-    fn update_score(&mut self, to_add: u32) {
-        self.score += to_add;
-    }
-
-    fn increase_level(&mut self) {
-        self.current_level += 1;
-    }
-
-    fn increase_line(&mut self) {
-        self.nb_lines += 1;
-        if self.nb_lines > LEVEL_LINES[self.current_level as usize - 1] {
-            self.increase_level();
-        }
-    }
-
-    fn check_lines(&mut self) {
-        let mut y = 0;
-        let mut score_add = 0;
-
-        while y < self.game_map.len() {
-            let mut complete = true;
-
-            for x in &self.game_map[y] {
-                if *x == 0 {
-                    complete = false;
-                    break;
-                }
-            }
-            if complete {
-                score_add += self.current_level;
-                self.game_map.remove(y);
-                y -= 1;
-            }
-            y += 1;
-        }
-        if self.game_map.is_empty() {
-            // A "tetris"!
-            score_add += 1000;
-        }
-        self.update_score(score_add);
-        while self.game_map.len() < 16 {
-            self.increase_line();
-            self.game_map.insert(0, vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
-        }
-    }
-
-    fn make_permanent(&mut self) {
-        let mut to_add = 0;
-        if let Some(ref mut piece) = self.current_piece {
-            let mut shift_y = 0;
-
-            while shift_y < piece.states[piece.current_state as usize].len()
-                && piece.y + shift_y < self.game_map.len()
-            {
-                let mut shift_x = 0;
-
-                while shift_x < piece.states[piece.current_state as usize][shift_y].len()
-                    && (piece.x + shift_x as isize)
-                        < self.game_map[piece.y + shift_y].len() as isize
-                {
-                    if piece.states[piece.current_state as usize][shift_y][shift_x] != 0 {
-                        let x = piece.x + shift_x as isize;
-                        self.game_map[piece.y + shift_y][x as usize] =
-                            piece.states[piece.current_state as usize][shift_y][shift_x];
-                    }
-                    shift_x += 1;
-                }
-                shift_y += 1;
-            }
-            to_add += self.current_level;
+impl Layout {
+    /// Fits a playfield of `columns` by `rows` cells in a window of at least
+    /// `width` by `height` pixels, the window growing if cells would be too small.
+    fn new(columns: usize, rows: usize, width: u32, height: u32) -> Layout {
+        let cell = ((height - 20 - TIMELINE_HEIGHT) / rows as u32)
+            .min(TETRIS_HEIGHT as u32)
+            .max(8);
+        let grid_width = cell * columns as u32;
+        let grid_height = cell * rows as u32;
+        let box_size = cell * 4 + 20;
+        let hold_box_y = NEXT_BOX_Y + box_size as i32 + 50;
+        let height = height
+            .max(grid_height + 20 + TIMELINE_HEIGHT)
+            .max(hold_box_y as u32 + box_size + 10);
+        let grid_x = 10;
+        let grid_y = (height - TIMELINE_HEIGHT - grid_height) as i32 / 2;
+        let panel_x = grid_x + grid_width as i32 + 10;
+        let queue_x = panel_x + 10 + box_size as i32 + 10;
+        Layout {
+            cell,
+            grid_x,
+            grid_y,
+            grid_width,
+            grid_height,
+            panel_x,
+            box_x: panel_x + 10,
+            box_size,
+            next_box_y: NEXT_BOX_Y,
+            hold_box_y,
+            queue_x,
+            timeline_y: grid_y + grid_height as i32 + 15,
+            width: (queue_x as u32 + cell * 2 + 10)
+                .max(panel_x as u32 + PANEL_WIDTH)
+                .max(width),
+            height,
         }
-        self.update_score(to_add);
-        self.check_lines();
-        self.current_piece = None;
     }
 }
 
@@ -172,6 +126,52 @@ fn create_texture_rect<'a>(
     }
 }
 
+/// Textures the game is drawn with, in the colors of a theme.
+struct Skin<'a> {
+    background: Color,
+    border: Texture<'a>,
+    grid: Texture<'a>,
+    ghost: Texture<'a>,
+    cells: [Texture<'a>; NUM_TEXTURES],
+}
+
+impl<'a> Skin<'a> {
+    fn new(
+        canvas: &mut Canvas<Window>,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        theme: Theme,
+        layout: &Layout,
+    ) -> Skin<'a> {
+        let mut texture = |(r, g, b): (u8, u8, u8), width, height| {
+            create_texture_rect(canvas, texture_creator, r, g, b, width, height)
+                .expect("Failed to create a texture")
+        };
+        let cell = layout.cell;
+        let [p1, p2, p3, p4, p5, p6, p7] = theme.pieces();
+        let (r, g, b) = theme.background();
+        Skin {
+            background: Color::RGB(r, g, b),
+            border: texture(
+                theme.border(),
+                layout.grid_width + 20,
+                layout.grid_height + 20,
+            ),
+            grid: texture((0, 0, 0), layout.grid_width, layout.grid_height),
+            ghost: texture(theme.ghost(), layout.grid_width, layout.grid_height),
+            cells: [
+                texture(p1, cell, cell),
+                texture(p2, cell, cell),
+                texture(p3, cell, cell),
+                texture(p4, cell, cell),
+                texture(p5, cell, cell),
+                texture(p6, cell, cell),
+                texture(p7, cell, cell),
+                texture(theme.garbage(), cell, cell),
+            ],
+        }
+    }
+}
+
 fn create_texture_from_text<'a>(
     texture_creator: &'a TextureCreator<WindowContext>,
     font: &sdl2::ttf::Font,
@@ -191,124 +191,333 @@ fn get_rect_from_text(text: &str, x: i32, y: i32) -> Option<Rect> {
     Some(Rect::new(x, y, text.len() as u32 * 20, 30))
 }
 
+/// Like `get_rect_from_text`, squeezed to fit the side panel.
+fn get_panel_rect_from_text(text: &str, x: i32, y: i32) -> Option<Rect> {
+    let width = (text.len() as u32 * 20).min(PANEL_WIDTH - 20);
+    Some(Rect::new(x, y, width, 30))
+}
+
 enum Cmd {
     Quit,
     Escape,
     Restart,
     Pause,
+    Review,
+    SaveQuit,
+    Controls,
+    Settings,
+    /// Puts the finished game on the leaderboard, under the name typed in.
+    Record,
+}
+
+/// A finished game played again from its history, to look back at mistakes.
+struct Review {
+    history: History,
+    /// The game as it was at the end of frame `position`, rounded down.
+    tetris: Tetris,
+    position: f64,
+    playing: bool,
+    /// Index in `REVIEW_SPEEDS`.
+    speed: usize,
+    /// Piece number being typed in, to jump to.
+    piece_entry: String,
 }
 
+impl Review {
+    fn new(history: History) -> Review {
+        Review {
+            tetris: history.seek(0),
+            history,
+            position: 0.,
+            playing: true,
+            speed: 2,
+            piece_entry: String::new(),
+        }
+    }
+
+    fn go_to(&mut self, frame: u64) {
+        if frame >= self.tetris.frame {
+            self.history.forward(&mut self.tetris, frame);
+        } else {
+            self.tetris = self.history.seek(frame);
+        }
+    }
+
+    /// Moves on by `frames` real frames, at the review speed.
+    fn advance(&mut self, frames: u32) {
+        if !self.playing {
+            return;
+        }
+        let end = self.history.end as f64;
+        self.position = (self.position + frames as f64 * REVIEW_SPEEDS[self.speed]).min(end);
+        if self.position >= end {
+            self.playing = false;
+        }
+        self.go_to(self.position as u64);
+    }
+
+    fn toggle_play(&mut self) {
+        if !self.playing && self.tetris.frame >= self.history.end {
+            // watch it again from the start
+            self.jump(0);
+        }
+        self.playing = !self.playing;
+    }
+
+    fn jump(&mut self, frame: u64) {
+        self.position = frame.min(self.history.end) as f64;
+        self.go_to(self.position as u64);
+    }
+
+    fn step_frame(&mut self, forward: bool) {
+        self.playing = false;
+        let frame = if forward {
+            self.tetris.frame + 1
+        } else {
+            self.tetris.frame.saturating_sub(1)
+        };
+        self.jump(frame);
+    }
+
+    fn jump_to_piece(&mut self) {
+        if let Some(frame) = self
+            .piece_entry
+            .parse()
+            .ok()
+            .and_then(|piece| self.history.piece_frame(piece))
+        {
+            self.jump(frame);
+        }
+        self.piece_entry.clear();
+    }
+
+    /// The two lines shown at the top of the panel.
+    fn status(&self) -> [String; 2] {
+        let speed = format!(
+            "{} x{}",
+            if self.playing { "Review" } else { "Paused" },
+            REVIEW_SPEEDS[self.speed]
+        );
+        let piece = if self.piece_entry.is_empty() {
+            format!("Piece:{}", self.piece())
+        } else {
+            format!("Go to:{}", self.piece_entry)
+        };
+        [speed, piece]
+    }
+
+    /// Number of the piece in play, counting from 1.
+    fn piece(&self) -> usize {
+        self.history
+            .spawns
+            .partition_point(|frame| *frame <= self.tetris.frame)
+    }
+}
+
+/// Command line options.
+#[derive(Default)]
+struct Options {
+    /// Where to write the replay of each game that ends.
+    record: Option<String>,
+    /// Replay file to watch instead of playing.
+    replay: Option<String>,
+    /// Directories to use instead of the player's usual ones.
+    config_dir: Option<String>,
+    data_dir: Option<String>,
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--record" => &mut options.record,
+            "--replay" => &mut options.replay,
+            "--config-dir" => &mut options.config_dir,
+            "--data-dir" => &mut options.data_dir,
+            _ => return Err(format!("unknown option '{}'", arg)),
+        };
+        *value = Some(args.next().ok_or(format!("{} needs a path", arg))?);
+    }
+    Ok(options)
+}
+
+/// Polls the player's events. Their inputs go through `history`, or are
+/// ignored while watching a replay, which brings its own inputs.
 fn handle_events(
     tetris: &mut Tetris,
+    mut history: Option<&mut History>,
+    bindings: &Bindings,
     cmd: &mut Option<Cmd>,
-    timer: &mut SystemTime,
     event_pump: &mut sdl2::EventPump,
-) -> bool {
-    let mut make_permanent = false;
-    let is_pause = tetris.is_pause();
-    if let Some(ref mut piece) = tetris.current_piece {
-        let mut tmp_x = piece.x;
-        let mut tmp_y = piece.y;
-
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. } => {
-                    *cmd = Some(Cmd::Quit);
-                    break;
-                }
-                Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => {
-                    *cmd = Some(Cmd::Escape);
-                    break;
-                }
-                Event::KeyDown {
-                    //keycode: Some(Keycode::Space),
-                    keycode: Some(Keycode::P),
-                    ..
-                } => {
-                    *cmd = Some(Cmd::Pause);
-                    break;
-                }
-                Event::KeyDown {
-                    keycode: Some(Keycode::Down),
-                    ..
-                } => {
-                    *timer = SystemTime::now();
-                    tmp_y += 1;
-                }
-                Event::KeyDown {
-                    keycode: Some(Keycode::Right),
-                    ..
-                } => {
-                    tmp_x += 1;
-                }
-                Event::KeyDown {
-                    keycode: Some(Keycode::Left),
-                    ..
-                } => {
-                    tmp_x -= 1;
-                }
-                Event::KeyDown {
-                    keycode: Some(Keycode::Up),
-                    ..
-                } => {
-                    piece.rotate(&tetris.game_map);
-                }
-                Event::KeyDown {
-                    keycode: Some(k), ..
-                } if (k == Keycode::RCtrl
-                    || k == Keycode::LCtrl
-                    || k == Keycode::PageDown
-                    || k == Keycode::Space) =>
-                {
-                    if !is_pause {
-                        let x = piece.x;
-                        let mut y = piece.y;
-                        while piece.change_position(&tetris.game_map, x, y + 1) {
-                            y += 1;
-                        }
-                        make_permanent = true;
-                    }
-                }
-                _ => {}
+) {
+    for event in event_pump.poll_iter() {
+        let (keycode, pressed) = match event {
+            Event::Quit { .. } => {
+                *cmd = Some(Cmd::Quit);
+                break;
             }
+            Event::KeyDown {
+                keycode: Some(keycode),
+                repeat: false,
+                ..
+            } => (keycode, true),
+            Event::KeyUp {
+                keycode: Some(keycode),
+                ..
+            } => (keycode, false),
+            _ => continue,
+        };
+        if pressed && keycode == CONTROLS_KEY {
+            *cmd = Some(Cmd::Controls);
+            break;
         }
-        if !is_pause
-            && !make_permanent
-            && !piece.change_position(&tetris.game_map, tmp_x, tmp_y)
-            && tmp_y != piece.y
-        {
-            make_permanent = true;
+        if pressed && keycode == SETTINGS_KEY && tetris.is_pause() {
+            *cmd = Some(Cmd::Settings);
+            break;
         }
-    } else {
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => {
-                    *cmd = Some(Cmd::Quit);
-                    break;
+        let action = match bindings.action(keycode) {
+            Some(action) => action,
+            None => continue,
+        };
+        if let Some(input) = action.input() {
+            if let Some(ref mut history) = history {
+                if !tetris.is_game_over() {
+                    let event = if pressed {
+                        InputEvent::Press(input)
+                    } else {
+                        InputEvent::Release(input)
+                    };
+                    history.handle(tetris, event);
                 }
-                Event::KeyDown {
-                    keycode: Some(Keycode::F1),
-                    ..
-                } => {
-                    *cmd = Some(Cmd::Restart);
-                    break;
+            }
+            continue;
+        }
+        if !pressed {
+            continue;
+        }
+        let command = match action {
+            Action::End if tetris.is_game_over() => Cmd::Quit,
+            Action::End => Cmd::Escape,
+            Action::Restart if tetris.is_game_over() || tetris.is_pause() => Cmd::Restart,
+            Action::Review if tetris.is_game_over() => Cmd::Review,
+            Action::Pause if !tetris.is_game_over() => Cmd::Pause,
+            Action::SaveQuit if !tetris.is_game_over() => Cmd::SaveQuit,
+            _ => continue,
+        };
+        *cmd = Some(command);
+        break;
+    }
+}
+
+/// Polls the events of a menu screen, which handles the keys pressed
+/// through `press` and tells whether it is still open.
+fn handle_menu_events(
+    mut press: impl FnMut(Keycode) -> bool,
+    cmd: &mut Option<Cmd>,
+    event_pump: &mut sdl2::EventPump,
+) {
+    for event in event_pump.poll_iter() {
+        let keycode = match event {
+            Event::Quit { .. } => {
+                *cmd = Some(Cmd::Quit);
+                break;
+            }
+            Event::KeyDown {
+                keycode: Some(keycode),
+                repeat: false,
+                ..
+            } => keycode,
+            _ => continue,
+        };
+        if !press(keycode) {
+            *cmd = Some(Cmd::Escape);
+            break;
+        }
+    }
+}
+
+/// Polls the events while the player types their name in for the
+/// leaderboard: Enter keeps the game, Escape leaves it out.
+fn handle_name_events(name: &mut String, cmd: &mut Option<Cmd>, event_pump: &mut sdl2::EventPump) {
+    for event in event_pump.poll_iter() {
+        match event {
+            Event::Quit { .. } => {
+                *cmd = Some(Cmd::Quit);
+                break;
+            }
+            Event::TextInput { text, .. } => {
+                // the font only has ASCII
+                for c in text.chars().filter(|c| c.is_ascii_graphic() || *c == ' ') {
+                    if name.len() < MAX_NAME_LEN {
+                        name.push(c);
+                    }
                 }
-                _ => {}
             }
+            Event::KeyDown {
+                keycode: Some(Keycode::Backspace),
+                ..
+            } => {
+                name.pop();
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Return | Keycode::KpEnter),
+                ..
+            } => {
+                *cmd = Some(Cmd::Record);
+                break;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Escape),
+                ..
+            } => {
+                *cmd = Some(Cmd::Escape);
+                break;
+            }
+            _ => {}
         }
     }
-    if make_permanent {
-        tetris.make_permanent();
-        *timer = SystemTime::now();
+}
+
+fn handle_review_events(
+    review: &mut Review,
+    cmd: &mut Option<Cmd>,
+    event_pump: &mut sdl2::EventPump,
+) {
+    for event in event_pump.poll_iter() {
+        let keycode = match event {
+            Event::Quit { .. } => {
+                *cmd = Some(Cmd::Quit);
+                break;
+            }
+            Event::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } => keycode,
+            _ => continue,
+        };
+        match keycode {
+            Keycode::Escape => {
+                *cmd = Some(Cmd::Escape);
+                break;
+            }
+            Keycode::Space => review.toggle_play(),
+            Keycode::Up => review.speed = (review.speed + 1).min(REVIEW_SPEEDS.len() - 1),
+            Keycode::Down => review.speed = review.speed.saturating_sub(1),
+            Keycode::Right => review.step_frame(true),
+            Keycode::Left => review.step_frame(false),
+            Keycode::Return | Keycode::KpEnter => review.jump_to_piece(),
+            Keycode::Backspace => {
+                review.piece_entry.pop();
+            }
+            _ => {
+                // digits of a piece number
+                if let Ok(digit) = keycode.name().parse::<u8>() {
+                    review.piece_entry.push_str(&digit.to_string());
+                }
+            }
+        }
     }
-    make_permanent
 }
 
 fn write_into_file(content: &str, file_name: &str) -> io::Result<()> {
@@ -323,129 +532,114 @@ fn read_from_file(file_name: &str) -> io::Result<String> {
     Ok(content)
 }
 
-fn slice_to_string(slice: &[u32]) -> String {
-    slice
-        .iter()
-        .map(|highscore| highscore.to_string())
-        .collect::<Vec<String>>()
-        .join(" ")
-}
-
-fn save_highscores_and_lines(
-    score_file: &str,
-    highscores: &[u32],
-    number_of_lines: &[u32],
-) -> bool {
-    let s_highscores = slice_to_string(highscores);
-    let s_number_of_lines = slice_to_string(number_of_lines);
-    write_into_file(
-        &format!("{}\n{}\n", s_highscores, s_number_of_lines),
-        score_file,
-    )
-    .is_ok()
+/// Reads the leaderboard, migrating old score files; there is none yet
+/// if the file doesn't exist.
+fn load_leaderboard(score_file: &str) -> Result<Leaderboard, String> {
+    match read_from_file(score_file) {
+        Ok(text) => Leaderboard::parse(&text),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Leaderboard::default()),
+        Err(err) => Err(err.to_string()),
+    }
 }
 
-fn line_to_slice(line: &str) -> Vec<u32> {
-    line.split(' ')
-        .filter_map(|nb| nb.parse::<u32>().ok())
-        .collect()
+fn print_game_information(tetris: &Tetris) {
+    match tetris.game_over_reason() {
+        Some(reason) => println!("Game over: {}", reason.label()),
+        None => println!("Game over..."),
+    }
+    println!("Mode:            {:?}", tetris.rules.mode);
+    println!("Score:           {}", tetris.score);
+    println!("Number of lines: {}", tetris.nb_lines);
+    println!("Current level:   {}", tetris.current_level);
+    println!("Time:            {}", format_time(tetris.frame));
 }
 
-fn load_highscores_and_lines(score_file: &str) -> Option<(Vec<u32>, Vec<u32>)> {
-    if let Ok(content) = read_from_file(score_file) {
-        let mut lines = content
-            .splitn(3, '\n')
-            .map(|line| line_to_slice(line))
-            .collect::<Vec<_>>();
-        if lines.len() == 3 {
-            lines.pop();
-            let (lines_sent, highscores) = (lines.pop().unwrap(), lines.pop().unwrap());
-            Some((highscores, lines_sent))
-        } else {
-            None
-        }
-    } else {
-        None
+/// The finished game, as the leaderboard would keep it under `name`.
+fn game_entry(tetris: &Tetris, name: &str) -> Entry {
+    Entry {
+        name: name.to_string(),
+        mode: tetris.rules.mode,
+        score: tetris.score,
+        lines: tetris.nb_lines,
+        level: tetris.current_level,
+        frames: Some(tetris.frame),
+        date: Some(today()),
     }
 }
 
-fn update_vec(v: &mut Vec<u32>, value: u32) -> bool {
-    if v.len() < NB_HIGHSCORES {
-        v.push(value);
-        true
-    } else {
-        for entry in v.iter_mut() {
-            if value > *entry {
-                *entry = value;
-                return true;
-            }
+/// Adds the finished game to the leaderboard under `name`, and shows it.
+fn record_score(tetris: &Tetris, name: &str, score_file: &str) {
+    let mut leaderboard = match load_leaderboard(score_file) {
+        Ok(leaderboard) => leaderboard,
+        Err(err) => {
+            eprintln!(
+                "Not recording the score, can't read {}: {}",
+                score_file, err
+            );
+            return;
         }
-        false
+    };
+    let rank = leaderboard.insert(game_entry(tetris, name));
+    if let Err(err) = write_into_file(&leaderboard.to_text(), score_file) {
+        eprintln!("Couldn't save scores {}: {}", score_file, err);
     }
+    print_leaderboard(&leaderboard, tetris.rules.mode, rank);
 }
 
-fn print_game_information(tetris: &Tetris) {
-    let mut new_highest_highscore = true;
-    let mut new_highest_lines_sent = true;
-    if let Some((mut highscores, mut lines_sent)) = load_highscores_and_lines(&tetris.score_file) {
-        new_highest_highscore = update_vec(&mut highscores, tetris.score);
-        new_highest_lines_sent = update_vec(&mut lines_sent, tetris.nb_lines);
-        if new_highest_highscore || new_highest_lines_sent {
-            save_highscores_and_lines(&tetris.score_file, &highscores, &lines_sent);
-        }
-    } else {
-        save_highscores_and_lines(&tetris.score_file, &[tetris.score], &[tetris.nb_lines]);
-    }
-    println!("Game over...");
-    println!(
-        "Score:           {}{}",
-        tetris.score,
-        if new_highest_highscore {
-            " [NEW HIGHSCORE]"
-        } else {
-            ""
-        }
-    );
-    println!(
-        "Number of lines: {}{}",
-        tetris.nb_lines,
-        if new_highest_lines_sent {
-            " [NEW LINES_SENT]"
-        } else {
-            ""
-        }
-    );
-    println!("Current level:   {}", tetris.current_level);
+/// Prints the best games of `mode`, the one ranked `new` marked.
+fn print_leaderboard(leaderboard: &Leaderboard, mode: GameModeKind, new: Option<usize>) {
+    println!("{:?} leaderboard:", mode);
+    for (rank, entry) in leaderboard.entries(mode).enumerate() {
+        println!(
+            "{:>2}. {:<12} {:>8} {:>4} lines  level {:<2} {:>9}  {}{}",
+            rank + 1,
+            entry.name,
+            entry.score,
+            entry.lines,
+            entry.level,
+            entry.duration(),
+            entry.date.as_deref().unwrap_or("-"),
+            if new == Some(rank) { "  [NEW]" } else { "" }
+        );
+    }
 }
 
-fn display_game_information<'a>(
+fn display_game_information(
     tetris: &Tetris,
+    status: Option<[String; 2]>,
+    bindings: &Bindings,
     canvas: &mut Canvas<Window>,
-    texture_creator: &'a TextureCreator<WindowContext>,
+    texture_creator: &TextureCreator<WindowContext>,
     font: &sdl2::ttf::Font,
     start_x_point: i32,
 ) {
-    let score_text = format!("Score:{}", tetris.score);
-    let lines_sent_text = format!("Lines:{}", tetris.nb_lines);
-    let level_text = format!("Level:{}", tetris.current_level);
-
-    let score = create_texture_from_text(&texture_creator, &font, &score_text, 255, 255, 255)
-        .expect("Cannot render text");
-    let lines_sent =
-        create_texture_from_text(&texture_creator, &font, &lines_sent_text, 255, 255, 255)
+    if let Some([t1_text, t2_text]) = status {
+        let t1 = create_texture_from_text(texture_creator, font, &t1_text, 255, 255, 255)
             .expect("Cannot render text");
-    let level = create_texture_from_text(&texture_creator, &font, &level_text, 255, 255, 255)
-        .expect("Cannot render text");
-
-    if tetris.is_game_over() {
+        let t2 = create_texture_from_text(texture_creator, font, &t2_text, 255, 255, 255)
+            .expect("Cannot render text");
+        canvas
+            .copy(
+                &t1,
+                None,
+                get_panel_rect_from_text(&t1_text, start_x_point, 20),
+            )
+            .expect("Couldn't copy text");
+        canvas
+            .copy(
+                &t2,
+                None,
+                get_panel_rect_from_text(&t2_text, start_x_point, 55),
+            )
+            .expect("Couldn't copy text");
+    } else if tetris.is_game_over() {
         let game_over_text = "Game Over".to_string();
-        let restart_text = "F1 to restart".to_string();
+        let restart_text = format!("{} to restart", bindings.hint(Action::Restart));
         let game_over =
-            create_texture_from_text(&texture_creator, &font, &game_over_text, 255, 255, 255)
-                .expect("Cannot render text");
-        let restart =
-            create_texture_from_text(&texture_creator, &font, &restart_text, 255, 255, 255)
+            create_texture_from_text(texture_creator, font, &game_over_text, 255, 255, 255)
                 .expect("Cannot render text");
+        let restart = create_texture_from_text(texture_creator, font, &restart_text, 255, 255, 255)
+            .expect("Cannot render text");
         canvas
             .copy(
                 &game_over,
@@ -462,55 +656,145 @@ fn display_game_information<'a>(
             .expect("Couldn't copy text");
     } else if tetris.is_pause() {
         let t1_text = "Paused".to_string();
-        let t2_text = "Press Space..".to_string();
-        let t1 = create_texture_from_text(&texture_creator, &font, &t1_text, 255, 255, 255)
+        let t2_text = format!("{} save+quit", bindings.hint(Action::SaveQuit));
+        let t1 = create_texture_from_text(texture_creator, font, &t1_text, 255, 255, 255)
             .expect("Cannot render text");
-        let t2 = create_texture_from_text(&texture_creator, &font, &t2_text, 255, 255, 255)
+        let t2 = create_texture_from_text(texture_creator, font, &t2_text, 255, 255, 255)
             .expect("Cannot render text");
         canvas
             .copy(&t1, None, get_rect_from_text(&t1_text, start_x_point, 20))
             .expect("Couldn't copy text");
         canvas
-            .copy(&t2, None, get_rect_from_text(&t2_text, start_x_point, 55))
+            .copy(
+                &t2,
+                None,
+                get_panel_rect_from_text(&t2_text, start_x_point, 55),
+            )
             .expect("Couldn't copy text");
     } else {
-        let t2_text = "Esc to end".to_string();
-        let t2 = create_texture_from_text(&texture_creator, &font, &t2_text, 255, 255, 255)
+        let mut t1_text = String::new();
+        if tetris.back_to_back {
+            t1_text.push_str("B2B ");
+        }
+        if let Some(combo) = tetris.combo.filter(|combo| *combo > 0) {
+            t1_text.push_str(&format!("Combo:{}", combo));
+        }
+        if !t1_text.is_empty() {
+            let t1 = create_texture_from_text(texture_creator, font, &t1_text, 255, 255, 255)
+                .expect("Cannot render text");
+            canvas
+                .copy(&t1, None, get_rect_from_text(&t1_text, start_x_point, 20))
+                .expect("Couldn't copy text");
+        }
+        let t2_text = format!("{} to end", bindings.hint(Action::End));
+        let t2 = create_texture_from_text(texture_creator, font, &t2_text, 255, 255, 255)
             .expect("Cannot render text");
         canvas
-            .copy(&t2, None, get_rect_from_text(&t2_text, start_x_point, 55))
+            .copy(
+                &t2,
+                None,
+                get_panel_rect_from_text(&t2_text, start_x_point, 55),
+            )
             .expect("Couldn't copy text");
     }
+    // the stats the game mode cares about, most important first
+    for (stat, y) in tetris.mode().stats().iter().zip([90, 125, 160].iter()) {
+        let stat_text = tetris.stat(*stat);
+        let stat = create_texture_from_text(texture_creator, font, &stat_text, 255, 255, 255)
+            .expect("Cannot render text");
+        canvas
+            .copy(
+                &stat,
+                None,
+                get_panel_rect_from_text(&stat_text, start_x_point, *y),
+            )
+            .expect("Couldn't copy text");
+    }
+}
+
+/// draws a menu screen over the whole window, the selected line in yellow
+fn display_menu(
+    lines: &[(String, bool)],
+    canvas: &mut Canvas<Window>,
+    texture_creator: &TextureCreator<WindowContext>,
+    font: &sdl2::ttf::Font,
+) {
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.clear();
+    for (nb, (line, selected)) in lines.iter().enumerate() {
+        let blue = if *selected { 0 } else { 255 };
+        let text = create_texture_from_text(texture_creator, font, line, 255, 255, blue)
+            .expect("Cannot render text");
+        canvas
+            .copy(
+                &text,
+                None,
+                Rect::new(10, 10 + nb as i32 * 26, line.len() as u32 * 10, 20),
+            )
+            .expect("Couldn't copy text");
+    }
+}
+
+/// shows what the last locked piece achieved (T-spins, tetrises...) over the playfield
+fn display_action_label(
+    tetris: &Tetris,
+    canvas: &mut Canvas<Window>,
+    texture_creator: &TextureCreator<WindowContext>,
+    font: &sdl2::ttf::Font,
+    x: i32,
+    y: i32,
+) {
+    if let Some(label) = tetris.last_clear.and_then(|clear| clear.label()) {
+        let text = create_texture_from_text(texture_creator, font, &label, 255, 255, 255)
+            .expect("Cannot render text");
+        canvas
+            .copy(&text, None, get_rect_from_text(&label, x, y))
+            .expect("Couldn't copy text");
+    }
+}
+
+/// draws the review timeline under the playfield: line clears are marked
+/// brighter the more lines they cleared, the current frame in white
+fn display_timeline(review: &Review, layout: &Layout, canvas: &mut Canvas<Window>) {
+    let end = review.history.end.max(1);
+    let x_at = |frame: u64| layout.grid_x + (frame * (layout.grid_width as u64 - 2) / end) as i32;
+    canvas.set_draw_color(Color::RGB(64, 64, 64));
     canvas
-        .copy(
-            &score,
-            None,
-            get_rect_from_text(&score_text, start_x_point, 90),
-        )
-        .expect("Couldn't copy text");
-    canvas
-        .copy(
-            &lines_sent,
-            None,
-            get_rect_from_text(&score_text, start_x_point, 125),
-        )
-        .expect("Couldn't copy text");
+        .fill_rect(Rect::new(
+            layout.grid_x,
+            layout.timeline_y,
+            layout.grid_width,
+            10,
+        ))
+        .expect("Couldn't draw timeline");
+    for &(frame, lines) in &review.history.clears {
+        let shade = 95 + 40 * lines.min(4) as u8;
+        canvas.set_draw_color(Color::RGB(shade, shade, 0));
+        canvas
+            .fill_rect(Rect::new(x_at(frame), layout.timeline_y, 2, 10))
+            .expect("Couldn't draw timeline");
+    }
+    canvas.set_draw_color(Color::RGB(255, 255, 255));
     canvas
-        .copy(
-            &level,
-            None,
-            get_rect_from_text(&score_text, start_x_point, 160),
-        )
-        .expect("Couldn't copy text");
+        .fill_rect(Rect::new(
+            x_at(review.tetris.frame),
+            layout.timeline_y - 3,
+            2,
+            16,
+        ))
+        .expect("Couldn't draw timeline");
 }
 
-/// if texture is Some, it overrides tetrimino's texture
+/// if texture is Some, it overrides tetrimino's texture;
+/// cells above offs_y (in the vanish zone) are clipped
+#[allow(clippy::too_many_arguments)]
 fn display_tetrimino(
-    tetrimino: &tetrimino::Tetrimino,
+    tetrimino: &Tetrimino,
+    cell: u32,
     offs_x: i32,
     piece_x: isize,
     offs_y: i32,
-    piece_y: usize,
+    piece_y: isize,
     textures: &[sdl2::render::Texture<'_>; NUM_TEXTURES],
     texture: Option<&sdl2::render::Texture<'_>>,
     canvas: &mut Canvas<Window>,
@@ -520,7 +804,7 @@ fn display_tetrimino(
         .enumerate()
     {
         for (case_nb, case) in line.iter().enumerate() {
-            if *case == 0 {
+            if *case == 0 || piece_y + (line_nb as isize) < 0 {
                 continue;
             }
             canvas
@@ -532,10 +816,10 @@ fn display_tetrimino(
                     },
                     None,
                     Rect::new(
-                        offs_x + (piece_x + case_nb as isize) as i32 * TETRIS_HEIGHT as i32,
-                        offs_y + (piece_y + line_nb) as i32 * TETRIS_HEIGHT as i32,
-                        TETRIS_HEIGHT as u32,
-                        TETRIS_HEIGHT as u32,
+                        offs_x + (piece_x + case_nb as isize) as i32 * cell as i32,
+                        offs_y + (piece_y + line_nb as isize) as i32 * cell as i32,
+                        cell,
+                        cell,
                     ),
                 )
                 .expect("Couldn't copy texture into window");
@@ -543,33 +827,35 @@ fn display_tetrimino(
     }
 }
 
-fn display_next_piece<'a>(
-    tetris: &Tetris,
-    width: u32,
+/// draws a side box title, and the tetrimino inside the box starting at box_y
+#[allow(clippy::too_many_arguments)]
+fn display_side_piece(
+    title: &str,
+    tetrimino: Option<&Tetrimino>,
+    box_y: i32,
+    layout: &Layout,
     textures: &[sdl2::render::Texture<'_>; NUM_TEXTURES],
     canvas: &mut Canvas<Window>,
-    texture_creator: &'a TextureCreator<WindowContext>,
+    texture_creator: &TextureCreator<WindowContext>,
     font: &sdl2::ttf::Font,
-    start_x_point: i32,
 ) {
-    let next_text = "Next:".to_string();
-
-    let next = create_texture_from_text(&texture_creator, &font, &next_text, 255, 255, 255)
+    let text = create_texture_from_text(texture_creator, font, title, 255, 255, 255)
         .expect("Cannot render text");
 
     canvas
         .copy(
-            &next,
+            &text,
             None,
-            get_rect_from_text(&next_text, start_x_point, 195),
+            get_rect_from_text(title, layout.box_x + 5, box_y - 45),
         )
         .expect("Couldn't copy text");
-    if let Some(ref tetrimino) = tetris.next_piece {
+    if let Some(tetrimino) = tetrimino {
         display_tetrimino(
             tetrimino,
-            width as i32 - 180 + 10,
+            layout.cell,
+            layout.box_x + 10,
             0,
-            240 + 10,
+            box_y + 10,
             0,
             textures,
             None,
@@ -578,13 +864,26 @@ fn display_next_piece<'a>(
     }
 }
 
-fn is_time_over(tetris: &Tetris, timer: &SystemTime) -> bool {
-    match timer.elapsed() {
-        Ok(elapsed) => {
-            let millis = elapsed.as_secs() as u32 * 1000 + elapsed.subsec_millis();
-            millis > LEVEL_TIMES[tetris.current_level as usize - 1]
-        }
-        Err(_) => false,
+/// draws the pieces coming after the next one, at half size, beside the next box
+fn display_queue(
+    pieces: &[Tetrimino],
+    layout: &Layout,
+    textures: &[sdl2::render::Texture<'_>; NUM_TEXTURES],
+    canvas: &mut Canvas<Window>,
+) {
+    let cell = layout.cell / 2;
+    for (nb, tetrimino) in pieces.iter().enumerate() {
+        display_tetrimino(
+            tetrimino,
+            cell,
+            layout.queue_x,
+            0,
+            layout.next_box_y + 10 + nb as i32 * 3 * cell as i32,
+            0,
+            textures,
+            None,
+            canvas,
+        );
     }
 }
 
@@ -593,31 +892,117 @@ fn main() {
     let video_subsystem = sdl_context
         .video()
         .expect("Couldn't get SDL video subsystem");
-    let width = WIDTH;
-    let height = HEIGHT;
-    let mut timer = SystemTime::now();
+    let text_input = video_subsystem.text_input();
     let mut event_pump = sdl_context.event_pump().expect(
         "Failed to get
           SDL event pump",
     );
 
-    let grid_x = 10;
-    let grid_y = (height - TETRIS_HEIGHT as u32 * 16) as i32 / 2;
-    let root_dir = current_exe().unwrap();
-    let root_dir = root_dir
-        .parent()
-        .unwrap()
-        .parent()
-        .unwrap()
-        .parent()
-        .unwrap();
-    let score_file = root_dir.join(HIGHSCORE_FILE);
+    let options = parse_options().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        eprintln!(
+            "usage: tetris [--record <file>] [--replay <file>] [--config-dir <dir>] [--data-dir <dir>]"
+        );
+        eprintln!("replay files without a directory are kept in the data directory");
+        std::process::exit(1);
+    });
+    let dirs = Dirs::new(
+        options.config_dir.as_ref().map(PathBuf::from),
+        options.data_dir.as_ref().map(PathBuf::from),
+    )
+    .and_then(|dirs| dirs.create().map(|_| dirs))
+    .unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+    // scores are the only file older versions kept
+    migrate(HIGHSCORE_FILE, &dirs.data);
+    let score_file = dirs.data.join(HIGHSCORE_FILE);
     let score_file = score_file.to_str().unwrap();
-    let mut tetris = Tetris::new(score_file);
-    tetris.next_piece = Some(Tetrimino::create_new_tetrimino());
+    let save_file = dirs.data.join(SAVE_FILE);
+    let controls_file = dirs.config.join(CONTROLS_FILE);
+    let mut bindings = match read_from_file(controls_file.to_str().unwrap()) {
+        Ok(text) => Bindings::parse(&text).unwrap_or_else(|err| {
+            eprintln!("Ignoring controls {}: {}", controls_file.display(), err);
+            Bindings::default()
+        }),
+        Err(_) => Bindings::default(),
+    };
+    let settings_file = dirs.config.join(SETTINGS_FILE);
+    let mut settings = match read_from_file(settings_file.to_str().unwrap()) {
+        Ok(text) => {
+            let (settings, errors) = Settings::parse(&text);
+            for error in errors {
+                eprintln!("{}: {}", settings_file.display(), error);
+            }
+            settings
+        }
+        Err(_) => Settings::default(),
+    };
+    let record_file = options.record.as_ref().map(|file| dirs.replay(file));
+    let watched = options.replay.as_ref().map(|file| {
+        let file = dirs.replay(file);
+        read_from_file(file.to_str().unwrap())
+            .map_err(|err| err.to_string())
+            .and_then(|text| Replay::parse(&text))
+            .unwrap_or_else(|err| {
+                eprintln!("Couldn't load replay {}: {}", file.display(), err);
+                std::process::exit(1);
+            })
+    });
+    let new_game = |settings: &Settings| match watched {
+        Some(ref replay) => Tetris::with_rules(replay.rules, replay.seed),
+        None => Tetris::with_rules(settings.rules(), rand::random()),
+    };
+    let mut tetris = new_game(&settings);
+    // a game saved last time is offered to be resumed, paused until the player decides
+    let mut offered = false;
+    if watched.is_none() && save_file.exists() {
+        match read_from_file(save_file.to_str().unwrap())
+            .map_err(|err| err.to_string())
+            .and_then(|text| Tetris::load(&text))
+        {
+            Ok(saved) => {
+                tetris = saved;
+                offered = true;
+            }
+            Err(err) => eprintln!("Ignoring saved game {}: {}", save_file.display(), err),
+        }
+    }
+    // replays start from a new game, they can't tell how a resumed one started
+    let mut resumed = offered;
+    // the history starts from the game running, for review to be able to play it
+    let mut history = History::new(&tetris);
+    if offered {
+        tetris.toggle_pause();
+    }
+    if let Some(ref replay) = watched {
+        history.feed(&mut tetris, replay);
+    }
+    let mut review: Option<Review> = None;
+    // name typed in for the leaderboard, once a game good enough for it ended
+    let mut name_entry: Option<String> = None;
+    let mut player_name = env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "Player".to_string());
+    player_name.retain(|c| c.is_ascii_graphic());
+    player_name.truncate(MAX_NAME_LEN);
+    if player_name.is_empty() {
+        player_name = "Player".to_string();
+    }
+    let mut controls: Option<ControlsScreen> = None;
+    let mut settings_screen: Option<SettingsScreen> = None;
+    let layout = Layout::new(
+        tetris.rules.width,
+        tetris.rules.height,
+        settings.window_width,
+        settings.window_height,
+    );
+    let (grid_x, grid_y) = (layout.grid_x, layout.grid_y);
+    let hidden_rows = tetris.rules.hidden_rows;
 
     let window = video_subsystem
-        .window("Tetris", width, height)
+        .window("Tetris", layout.width, layout.height)
         .position_centered()
         .build()
         .expect("Failed to create window");
@@ -632,203 +1017,74 @@ fn main() {
     let texture_creator: TextureCreator<_> = canvas.texture_creator();
 
     let ttf_context = sdl2::ttf::init().expect("SDL TTF initialization failed");
-    let ttf_file = root_dir.join("assets").join("lucon.ttf");
+    let ttf_file = assets_dir().join("lucon.ttf");
     let mut font = ttf_context
         .load_font(ttf_file, 128)
         .expect("Couldn't load the font");
 
     font.set_style(sdl2::ttf::FontStyle::BOLD);
 
-    const GREY: u8 = 64;
-    let grey = create_texture_rect(
-        &mut canvas,
-        &texture_creator,
-        GREY,
-        GREY,
-        GREY,
-        TETRIS_HEIGHT as u32 * 10,
-        TETRIS_HEIGHT as u32 * 16,
-    )
-    .expect(
-        "Failed to create
-                 a texture",
-    );
-
-    let grid = create_texture_rect(
-        &mut canvas,
-        &texture_creator,
-        0,
-        0,
-        0,
-        TETRIS_HEIGHT as u32 * 10,
-        TETRIS_HEIGHT as u32 * 16,
-    )
-    .expect(
-        "Failed to create
-                 a texture",
-    );
-
-    let border = create_texture_rect(
-        &mut canvas,
-        &texture_creator,
-        255,
-        255,
-        255,
-        TETRIS_HEIGHT as u32 * 10 + 20,
-        TETRIS_HEIGHT as u32 * 16 + 20,
-    )
-    .expect(
-        "Failed to create
-                 a texture",
-    );
-
-    macro_rules! texture {
-        ($r:expr, $g:expr, $b:expr) => {
-            create_texture_rect(
-                &mut canvas,
-                &texture_creator,
-                $r,
-                $g,
-                $b,
-                TETRIS_HEIGHT as u32,
-                TETRIS_HEIGHT as u32,
-            )
-            .unwrap()
-        };
-    }
-
-    let textures = [
-        texture!(255, 69, 69),
-        texture!(255, 220, 69),
-        texture!(237, 150, 37),
-        texture!(171, 99, 237),
-        texture!(77, 149, 239),
-        texture!(39, 218, 225),
-        texture!(45, 216, 47),
-    ];
+    let mut skin = Skin::new(&mut canvas, &texture_creator, settings.theme, &layout);
 
+    // the game runs at a fixed number of ticks per second, whatever the display's refresh rate
+    let clock = RealClock::new();
+    let mut step = FixedStep::new(FRAME_RATE, &clock);
     loop {
-        if !tetris.is_pause() && is_time_over(&tetris, &timer) {
-            let mut make_permanent = false;
-            if let Some(ref mut piece) = tetris.current_piece {
-                let x = piece.x;
-                let y = piece.y + 1;
-                make_permanent = !piece.change_position(&tetris.game_map, x, y);
-            }
-            if make_permanent {
-                tetris.make_permanent();
+        let was_game_over = tetris.is_game_over();
+        let frames = step.due(&clock);
+        match review {
+            Some(ref mut review) => review.advance(frames),
+            None => {
+                for _ in 0..frames {
+                    let frame = tetris.frame;
+                    history.tick(&mut tetris);
+                    if let Some(ref replay) = watched {
+                        if tetris.frame != frame {
+                            history.feed(&mut tetris, replay);
+                        }
+                    }
+                }
             }
-            timer = SystemTime::now();
         }
 
-        canvas.set_draw_color(Color::RGB(255, 0, 0));
+        canvas.set_draw_color(skin.background);
         canvas.clear();
 
         canvas
             .copy(
-                &border,
+                &skin.border,
                 None,
                 Rect::new(
                     grid_x - 10,
                     grid_y - 10,
-                    TETRIS_HEIGHT as u32 * 10 + 20,
-                    TETRIS_HEIGHT as u32 * 16 + 20,
+                    layout.grid_width + 20,
+                    layout.grid_height + 20,
                 ),
             )
             .expect("Couldn't copy texture into window");
         canvas
             .copy(
-                &grid,
+                &skin.grid,
                 None,
-                Rect::new(
-                    grid_x,
-                    grid_y,
-                    TETRIS_HEIGHT as u32 * 10,
-                    TETRIS_HEIGHT as u32 * 16,
-                ),
+                Rect::new(grid_x, grid_y, layout.grid_width, layout.grid_height),
             )
             .expect("Couldn't copy texture into window");
 
-        canvas
-            .copy(
-                &border,
-                None,
-                Rect::new(
-                    width as i32 - 180,
-                    240,
-                    TETRIS_HEIGHT as u32 * 4 + 20,
-                    TETRIS_HEIGHT as u32 * 4 + 20,
-                ),
-            )
-            .expect("Couldn't copy texture into window");
-        canvas
-            .copy(
-                &grid,
-                None,
-                Rect::new(
-                    width as i32 - 180 + 10,
-                    240 + 10,
-                    TETRIS_HEIGHT as u32 * 4,
-                    TETRIS_HEIGHT as u32 * 4,
-                ),
-            )
-            .expect("Couldn't copy texture into window");
-
-        if !tetris.is_pause() && !tetris.is_game_over() && tetris.current_piece.is_none() {
-            // we need to take ownership of the option value, to move it to tetris.current_piece
-            if let Some(current_piece) = tetris.next_piece.take() {
-                if !current_piece.test_current_position(&tetris.game_map) {
-                    print_game_information(&tetris);
-                    tetris.state = State::GameOver;
-                }
-                if !tetris.is_game_over() {
-                    // consume next piece
-                    tetris.current_piece = Some(current_piece);
-                    tetris.next_piece = Some(Tetrimino::create_new_tetrimino());
-                } else {
-                    // restore next piece
-                    tetris.next_piece = Some(current_piece);
-                }
-            }
-        }
         let mut cmd = None;
-        if !handle_events(&mut tetris, &mut cmd, &mut timer, &mut event_pump) {
-            if let Some(ref mut tetrimino) = tetris.current_piece {
-                // draw ghost
-                let x = tetrimino.x;
-                let mut y = tetrimino.y;
-                while tetrimino.test_position(
-                    &tetris.game_map,
-                    tetrimino.current_state as usize,
-                    x,
-                    y + 1,
-                ) {
-                    y += 1;
-                }
-                if y > tetrimino.y {
-                    display_tetrimino(
-                        tetrimino,
-                        grid_x,
-                        x,
-                        grid_y,
-                        y,
-                        &textures,
-                        Some(&grey),
-                        &mut canvas,
-                    );
-                }
-                // draw current
-                display_tetrimino(
-                    tetrimino,
-                    grid_x,
-                    tetrimino.x,
-                    grid_y,
-                    tetrimino.y,
-                    &textures,
-                    None,
-                    &mut canvas,
-                );
-            }
+        if let Some(ref mut screen) = controls {
+            handle_menu_events(|key| screen.press(key), &mut cmd, &mut event_pump);
+        } else if let Some(ref mut screen) = settings_screen {
+            handle_menu_events(|key| screen.press(key), &mut cmd, &mut event_pump);
+        } else if let Some(ref mut name) = name_entry {
+            handle_name_events(name, &mut cmd, &mut event_pump);
+        } else if let Some(ref mut review) = review {
+            handle_review_events(review, &mut cmd, &mut event_pump);
+        } else {
+            let player = match watched {
+                Some(_) => None,
+                None => Some(&mut history),
+            };
+            handle_events(&mut tetris, player, &bindings, &mut cmd, &mut event_pump);
         }
         if let Some(cmd) = cmd {
             match cmd {
@@ -836,71 +1092,339 @@ fn main() {
                     break;
                 }
                 Cmd::Escape => {
-                    if tetris.is_game_over() {
+                    if let Some(screen) = controls.take() {
+                        if screen.changed {
+                            bindings = screen.bindings;
+                            let file = controls_file.to_str().unwrap();
+                            if let Err(err) = write_into_file(&bindings.to_text(), file) {
+                                eprintln!("Couldn't save controls {}: {}", file, err);
+                            }
+                        }
+                    } else if let Some(screen) = settings_screen.take() {
+                        if screen.changed {
+                            if screen.settings.theme != settings.theme {
+                                skin = Skin::new(
+                                    &mut canvas,
+                                    &texture_creator,
+                                    screen.settings.theme,
+                                    &layout,
+                                );
+                            }
+                            settings = screen.settings;
+                            let file = settings_file.to_str().unwrap();
+                            if let Err(err) = write_into_file(&settings.to_text(), file) {
+                                eprintln!("Couldn't save settings {}: {}", file, err);
+                            }
+                        }
+                    } else if name_entry.take().is_some() {
+                        text_input.stop();
+                        if let Ok(leaderboard) = load_leaderboard(score_file) {
+                            print_leaderboard(&leaderboard, tetris.rules.mode, None);
+                        }
+                    } else if review.is_some() {
+                        review = None;
+                    } else if tetris.is_game_over() || watched.is_some() {
                         break;
                     } else {
-                        print_game_information(&tetris);
-                        tetris.current_piece = None;
-                        tetris.state = State::GameOver;
+                        history.quit(&mut tetris);
                     }
                 }
                 Cmd::Restart => {
-                    if tetris.is_game_over() {
-                        tetris = Tetris::new(score_file);
-                        tetris.next_piece = Some(Tetrimino::create_new_tetrimino());
+                    // a paused game, the saved one offered included, is given up
+                    if tetris.is_game_over() || tetris.is_pause() {
+                        tetris = new_game(&settings);
+                        history = History::new(&tetris);
+                        if let Some(ref replay) = watched {
+                            history.feed(&mut tetris, replay);
+                        }
+                        resumed = false;
                     }
                 }
                 Cmd::Pause => {
-                    if tetris.is_pause() {
-                        timer = SystemTime::now();
-                        tetris.state = State::Running;
-                    } else {
-                        tetris.state = State::Pause;
+                    tetris.toggle_pause();
+                }
+                Cmd::SaveQuit => {
+                    if watched.is_none() {
+                        match write_into_file(&tetris.save(), save_file.to_str().unwrap()) {
+                            Ok(()) => break,
+                            Err(err) => eprintln!("Couldn't save the game: {}", err),
+                        }
                     }
                 }
+                Cmd::Controls => {
+                    if !tetris.is_pause() && !tetris.is_game_over() {
+                        tetris.toggle_pause();
+                    }
+                    controls = Some(ControlsScreen::new(bindings.clone()));
+                }
+                Cmd::Settings => {
+                    settings_screen = Some(SettingsScreen::new(settings));
+                }
+                Cmd::Record => {
+                    if let Some(name) = name_entry.take() {
+                        text_input.stop();
+                        if !name.trim().is_empty() {
+                            player_name = name.trim().to_string();
+                        }
+                        record_score(&tetris, &player_name, score_file);
+                    }
+                }
+                Cmd::Review => {
+                    println!("Review: Space play/pause, Up/Down speed, Left/Right one frame,");
+                    println!("        piece number then Enter to jump, Esc to go back");
+                    review = Some(Review::new(history.clone()));
+                }
+            }
+        }
+        if offered && !tetris.is_pause() {
+            // resumed or replaced by a new game, the saved game is used up either way
+            offered = false;
+            if let Err(err) = std::fs::remove_file(&save_file) {
+                eprintln!("Couldn't remove {}: {}", save_file.display(), err);
+            }
+        }
+        if !was_game_over && tetris.is_game_over() {
+            if watched.is_some() {
+                println!(
+                    "Replay over: score {}, {} lines, level {}",
+                    tetris.score, tetris.nb_lines, tetris.current_level
+                );
+            } else {
+                print_game_information(&tetris);
+                let ranks = tetris
+                    .game_over_reason()
+                    .is_some_and(|reason| tetris.mode().ranks(reason));
+                match load_leaderboard(score_file) {
+                    Ok(leaderboard) => match leaderboard.rank(&game_entry(&tetris, "")) {
+                        Some(_) if ranks => {
+                            name_entry = Some(player_name.clone());
+                            text_input.start();
+                        }
+                        _ => print_leaderboard(&leaderboard, tetris.rules.mode, None),
+                    },
+                    Err(err) => {
+                        eprintln!(
+                            "Not recording the score, can't read {}: {}",
+                            score_file, err
+                        )
+                    }
+                }
+                if let Some(ref file) = record_file {
+                    let file = file.to_str().unwrap();
+                    if resumed {
+                        eprintln!("Not saving replay {}: the game was resumed", file);
+                    } else if let Err(err) = write_into_file(&history.replay.to_text(), file) {
+                        eprintln!("Couldn't save replay {}: {}", file, err);
+                    }
+                }
+            }
+        }
+
+        let menu = match (&controls, &settings_screen) {
+            (Some(screen), _) => Some(screen.lines()),
+            (None, Some(screen)) => Some(screen.lines()),
+            (None, None) => None,
+        };
+        if let Some(lines) = menu {
+            display_menu(&lines, &mut canvas, &texture_creator, &font);
+            canvas.present();
+            continue;
+        }
+
+        // what is on screen: the game, or the one being reviewed
+        let shown = review.as_ref().map_or(&tetris, |review| &review.tetris);
+
+        let nb_boxes = if shown.rules.hold { 2 } else { 1 };
+        for &box_y in [layout.next_box_y, layout.hold_box_y].iter().take(nb_boxes) {
+            canvas
+                .copy(
+                    &skin.border,
+                    None,
+                    Rect::new(layout.box_x, box_y, layout.box_size, layout.box_size),
+                )
+                .expect("Couldn't copy texture into window");
+            canvas
+                .copy(
+                    &skin.grid,
+                    None,
+                    Rect::new(
+                        layout.box_x + 10,
+                        box_y + 10,
+                        layout.box_size - 20,
+                        layout.box_size - 20,
+                    ),
+                )
+                .expect("Couldn't copy texture into window");
+        }
+
+        if let (Some(tetrimino), Some(y)) = (&shown.current_piece, shown.ghost_y()) {
+            // draw ghost
+            if settings.ghost && shown.rules.ghost && y > tetrimino.y {
+                display_tetrimino(
+                    tetrimino,
+                    layout.cell,
+                    grid_x,
+                    tetrimino.x,
+                    grid_y,
+                    y - hidden_rows as isize,
+                    &skin.cells,
+                    Some(&skin.ghost),
+                    &mut canvas,
+                );
             }
+            // draw current
+            display_tetrimino(
+                tetrimino,
+                layout.cell,
+                grid_x,
+                tetrimino.x,
+                grid_y,
+                tetrimino.y - hidden_rows as isize,
+                &skin.cells,
+                None,
+                &mut canvas,
+            );
         }
 
-        for (line_nb, line) in tetris.game_map.iter().enumerate() {
+        // blocks locked in the vanish zone are not drawn
+        for (line_nb, line) in shown.game_map.iter().skip(hidden_rows).enumerate() {
             for (case_nb, case) in line.iter().enumerate() {
                 if *case == 0 {
                     continue;
                 }
                 canvas
                     .copy(
-                        &textures[*case as usize - 1],
+                        &skin.cells[*case as usize - 1],
                         None,
                         Rect::new(
-                            grid_x + case_nb as i32 * TETRIS_HEIGHT as i32,
-                            grid_y + line_nb as i32 * TETRIS_HEIGHT as i32,
-                            TETRIS_HEIGHT as u32,
-                            TETRIS_HEIGHT as u32,
+                            grid_x + case_nb as i32 * layout.cell as i32,
+                            grid_y + line_nb as i32 * layout.cell as i32,
+                            layout.cell,
+                            layout.cell,
                         ),
                     )
                     .expect("Couldn't copy texture into window");
             }
         }
 
+        // cleared rows fade out towards the middle until the line clear delay is over
+        if shown.phase() == Phase::LineClear {
+            let total = shown.delays().line_clear.max(1);
+            let width = layout.cell * shown.rules.width as u32 * shown.delay_left() / total;
+            let x = grid_x + (layout.cell * shown.rules.width as u32 - width) as i32 / 2;
+            canvas.set_draw_color(Color::RGB(255, 255, 255));
+            for &y in shown.cleared_rows() {
+                if y < hidden_rows || width == 0 {
+                    continue;
+                }
+                let y = grid_y + (y - hidden_rows) as i32 * layout.cell as i32;
+                canvas
+                    .fill_rect(Rect::new(x, y, width, layout.cell))
+                    .expect("Couldn't draw a cleared row");
+            }
+        }
+
+        let status = match (&review, &name_entry) {
+            (Some(review), _) => Some(review.status()),
+            (None, Some(name)) => Some(["Your name:".to_string(), format!("{}_", name)]),
+            (None, None) if offered => Some([
+                format!("{} to resume", bindings.hint(Action::Pause)),
+                format!("{} new game", bindings.hint(Action::Restart)),
+            ]),
+            (None, None) => None,
+        };
         display_game_information(
-            &tetris,
+            shown,
+            status,
+            &bindings,
             &mut canvas,
             &texture_creator,
             &font,
-            TETRIS_HEIGHT as i32 * 10 + 20,
+            layout.panel_x,
         );
 
-        display_next_piece(
-            &tetris,
-            width,
-            &textures,
+        display_action_label(
+            shown,
             &mut canvas,
             &texture_creator,
             &font,
-            width as i32 - grid_x - 180 + 15,
+            grid_x + 10,
+            grid_y + 10,
         );
 
-        canvas.present();
+        let preview = shown.preview(settings.previews as usize);
+        display_side_piece(
+            "Next:",
+            preview.first(),
+            layout.next_box_y,
+            &layout,
+            &skin.cells,
+            &mut canvas,
+            &texture_creator,
+            &font,
+        );
+        display_queue(
+            preview.get(1..).unwrap_or_default(),
+            &layout,
+            &skin.cells,
+            &mut canvas,
+        );
+        if shown.rules.hold {
+            display_side_piece(
+                "Hold:",
+                shown.hold_piece.as_ref(),
+                layout.hold_box_y,
+                &layout,
+                &skin.cells,
+                &mut canvas,
+                &texture_creator,
+                &font,
+            );
+        }
 
-        sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        // hints written over the playfield, squeezed to fit it
+        let hints = match review {
+            Some(ref review) => {
+                display_timeline(review, &layout, &mut canvas);
+                Vec::new()
+            }
+            None => match tetris.game_over_reason() {
+                Some(reason) if name_entry.is_some() => vec![
+                    reason.label().to_string(),
+                    "Enter to save".to_string(),
+                    "Esc to skip".to_string(),
+                ],
+                Some(reason) => vec![
+                    reason.label().to_string(),
+                    format!("{} to review", bindings.hint(Action::Review)),
+                ],
+                None if tetris.is_pause() => vec![
+                    format!("{} new game", bindings.hint(Action::Restart)),
+                    format!("{} controls", CONTROLS_KEY.name()),
+                    format!("{} settings", SETTINGS_KEY.name()),
+                ],
+                None => Vec::new(),
+            },
+        };
+        for (nb, text) in hints.iter().enumerate() {
+            let hint = create_texture_from_text(&texture_creator, &font, text, 255, 255, 255)
+                .expect("Cannot render text");
+            let width = (text.len() as u32 * 20).min(layout.grid_width - 20);
+            canvas
+                .copy(
+                    &hint,
+                    None,
+                    Rect::new(
+                        grid_x + 10,
+                        grid_y + layout.grid_height as i32 / 2 + nb as i32 * 35,
+                        width,
+                        30,
+                    ),
+                )
+                .expect("Couldn't copy text");
+        }
+
+        // waits for the display's refresh
+        canvas.present();
     }
 }
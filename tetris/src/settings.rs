@@ -0,0 +1,423 @@
+use sdl2::keyboard::Keycode;
+use tetris::engine::mode::GameModeKind;
+use tetris::engine::{parse_variant, Rules, FRAME_RATE, MAX_LEVEL};
+
+use std::fmt::{Debug, Write};
+
+/// Key opening the settings screen while paused.
+pub const SETTINGS_KEY: Keycode = Keycode::F4;
+
+const WINDOW_WIDTHS: (u32, u32) = (200, 4000);
+const WINDOW_HEIGHTS: (u32, u32) = (200, 4000);
+const DAS_TICKS: (u32, u32) = (0, 60);
+const ARR_TICKS: (u32, u32) = (0, 30);
+const PREVIEWS: (u32, u32) = (1, 5);
+const RISING_GARBAGE_SECONDS: (u32, u32) = (0, 30);
+
+/// Setting names, in the order they are written and shown.
+const NAMES: [&str; 10] = [
+    "window_width",
+    "window_height",
+    "mode",
+    "start_level",
+    "rising_garbage",
+    "das",
+    "arr",
+    "ghost",
+    "previews",
+    "theme",
+];
+
+/// Colors the game is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Theme {
+    Classic,
+    Dark,
+    Ocean,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Classic, Theme::Dark, Theme::Ocean];
+
+    pub fn background(self) -> (u8, u8, u8) {
+        match self {
+            Theme::Classic => (255, 0, 0),
+            Theme::Dark => (24, 24, 32),
+            Theme::Ocean => (16, 40, 88),
+        }
+    }
+
+    /// Around the playfield and the side boxes.
+    pub fn border(self) -> (u8, u8, u8) {
+        match self {
+            Theme::Classic => (255, 255, 255),
+            Theme::Dark => (96, 96, 112),
+            Theme::Ocean => (120, 190, 230),
+        }
+    }
+
+    pub fn ghost(self) -> (u8, u8, u8) {
+        match self {
+            Theme::Classic => (64, 64, 64),
+            Theme::Dark => (48, 48, 60),
+            Theme::Ocean => (40, 64, 96),
+        }
+    }
+
+    pub fn garbage(self) -> (u8, u8, u8) {
+        match self {
+            Theme::Classic => (128, 128, 128),
+            Theme::Dark => (84, 84, 92),
+            Theme::Ocean => (96, 120, 140),
+        }
+    }
+
+    /// One color per kind of piece.
+    pub fn pieces(self) -> [(u8, u8, u8); 7] {
+        match self {
+            Theme::Classic | Theme::Dark => [
+                (255, 69, 69),
+                (255, 220, 69),
+                (237, 150, 37),
+                (171, 99, 237),
+                (77, 149, 239),
+                (39, 218, 225),
+                (45, 216, 47),
+            ],
+            Theme::Ocean => [
+                (250, 128, 114),
+                (255, 236, 139),
+                (255, 179, 102),
+                (186, 150, 255),
+                (100, 170, 255),
+                (120, 240, 240),
+                (110, 230, 160),
+            ],
+        }
+    }
+}
+
+/// Options the player picks, kept from one session to the next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    /// Smallest window size, in pixels; the window grows if the playfield needs it.
+    pub window_width: u32,
+    pub window_height: u32,
+    pub mode: GameModeKind,
+    pub start_level: u32,
+    /// Seconds between two rows of garbage rising, 0 for none.
+    pub rising_garbage: u32,
+    /// Handling, in ticks, as in `Rules`.
+    pub das: u32,
+    pub arr: u32,
+    /// Whether to show where the piece would land.
+    pub ghost: bool,
+    /// Number of pieces shown coming next.
+    pub previews: u32,
+    pub theme: Theme,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let rules = Rules::default();
+        Settings {
+            window_width: 560,
+            window_height: 660,
+            mode: rules.mode,
+            start_level: rules.start_level,
+            rising_garbage: rules.rising_garbage / FRAME_RATE,
+            das: rules.das,
+            arr: rules.arr,
+            ghost: true,
+            previews: 1,
+            theme: Theme::Classic,
+        }
+    }
+}
+
+/// Reads a number, which must be within `min..=max`.
+fn number(value: &str, (min, max): (u32, u32)) -> Result<u32, String> {
+    match value.parse() {
+        Ok(number) if number >= min && number <= max => Ok(number),
+        _ => Err(format!(
+            "invalid value '{}', expected {} to {}",
+            value, min, max
+        )),
+    }
+}
+
+/// Reads one of `variants`, written as its name.
+fn variant<T: Debug + Copy>(variants: &[T], value: &str, what: &str) -> Result<T, String> {
+    parse_variant(variants, value).ok_or_else(|| format!("unknown {} '{}'", what, value))
+}
+
+/// The variant `steps` after `current` in `variants`, going round.
+fn cycle<T: PartialEq + Copy>(variants: &[T], current: T, steps: i32) -> T {
+    let index = variants.iter().position(|variant| *variant == current);
+    let index = (index.unwrap_or(0) as i32 + steps).rem_euclid(variants.len() as i32);
+    variants[index as usize]
+}
+
+impl Settings {
+    /// Rules for new games, the default ones but for what the player
+    /// picked; the game mode then has the last word.
+    pub fn rules(&self) -> Rules {
+        Rules {
+            mode: self.mode,
+            start_level: self.start_level,
+            rising_garbage: self.rising_garbage * FRAME_RATE,
+            das: self.das,
+            arr: self.arr,
+            ..Rules::default()
+        }
+    }
+
+    /// Levels games of the chosen mode can start at.
+    fn start_levels(&self) -> (u32, u32) {
+        self.mode.create().start_levels()
+    }
+
+    /// Switches to `mode`, bringing the start level within its levels.
+    fn set_mode(&mut self, mode: GameModeKind) {
+        self.mode = mode;
+        let (min, max) = self.start_levels();
+        self.start_level = self.start_level.clamp(min, max);
+    }
+
+    /// The setting called `name`, as written in the settings file.
+    pub fn get(&self, name: &str) -> String {
+        match name {
+            "window_width" => self.window_width.to_string(),
+            "window_height" => self.window_height.to_string(),
+            "mode" => format!("{:?}", self.mode),
+            "start_level" => self.start_level.to_string(),
+            "rising_garbage" => self.rising_garbage.to_string(),
+            "das" => self.das.to_string(),
+            "arr" => self.arr.to_string(),
+            "ghost" => self.ghost.to_string(),
+            "previews" => self.previews.to_string(),
+            "theme" => format!("{:?}", self.theme),
+            _ => String::new(),
+        }
+    }
+
+    /// Sets the setting called `name` from its text value. The start level
+    /// is only checked against every mode's levels, the mode may come after.
+    /// Returns Ok(false) if there is no such setting.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<bool, String> {
+        match name {
+            "window_width" => self.window_width = number(value, WINDOW_WIDTHS)?,
+            "window_height" => self.window_height = number(value, WINDOW_HEIGHTS)?,
+            "mode" => self.mode = variant(&GameModeKind::ALL, value, "mode")?,
+            "start_level" => self.start_level = number(value, (0, MAX_LEVEL))?,
+            "rising_garbage" => self.rising_garbage = number(value, RISING_GARBAGE_SECONDS)?,
+            "das" => self.das = number(value, DAS_TICKS)?,
+            "arr" => self.arr = number(value, ARR_TICKS)?,
+            "ghost" => {
+                self.ghost = value
+                    .parse()
+                    .map_err(|_| format!("invalid value '{}', expected true or false", value))?
+            }
+            "previews" => self.previews = number(value, PREVIEWS)?,
+            "theme" => self.theme = variant(&Theme::ALL, value, "theme")?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Changes the setting called `name` by `steps` notches up or down,
+    /// staying within what it allows.
+    pub fn adjust(&mut self, name: &str, steps: i32) {
+        let add = |value: u32, step: i64, (min, max): (u32, u32)| {
+            (value as i64 + steps as i64 * step).clamp(min as i64, max as i64) as u32
+        };
+        match name {
+            "window_width" => self.window_width = add(self.window_width, 20, WINDOW_WIDTHS),
+            "window_height" => self.window_height = add(self.window_height, 20, WINDOW_HEIGHTS),
+            "mode" => self.set_mode(cycle(&GameModeKind::ALL, self.mode, steps)),
+            "start_level" => self.start_level = add(self.start_level, 1, self.start_levels()),
+            "rising_garbage" => {
+                self.rising_garbage = add(self.rising_garbage, 1, RISING_GARBAGE_SECONDS)
+            }
+            "das" => self.das = add(self.das, 1, DAS_TICKS),
+            "arr" => self.arr = add(self.arr, 1, ARR_TICKS),
+            "ghost" => self.ghost = !self.ghost,
+            "previews" => self.previews = add(self.previews, 1, PREVIEWS),
+            "theme" => self.theme = cycle(&Theme::ALL, self.theme, steps),
+            _ => {}
+        }
+    }
+
+    /// Reads settings as written by `to_text`. Settings left out or that
+    /// can't be read keep their default value; the second member of the
+    /// result tells what was wrong, line by line.
+    pub fn parse(text: &str) -> (Settings, Vec<String>) {
+        let mut settings = Settings::default();
+        let mut errors = Vec::new();
+        let mut start_level_line = None;
+        for (nb, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            if name == "start_level" {
+                start_level_line = Some(nb + 1);
+            }
+            match settings.set(name, value.trim()) {
+                Ok(true) => {}
+                Ok(false) => errors.push(format!("line {}: unknown setting '{}'", nb + 1, name)),
+                Err(err) => errors.push(format!("line {}: {}: {}", nb + 1, name, err)),
+            }
+        }
+        // the start level fits the mode, wherever the two are in the file
+        let (min, max) = settings.start_levels();
+        if settings.start_level < min || settings.start_level > max {
+            if let Some(nb) = start_level_line {
+                errors.push(format!(
+                    "line {}: start_level: {:?} starts at levels {} to {}",
+                    nb, settings.mode, min, max
+                ));
+            }
+            settings.start_level = settings.start_level.clamp(min, max);
+        }
+        (settings, errors)
+    }
+
+    pub fn to_text(self) -> String {
+        let mut text = String::new();
+        for name in NAMES.iter() {
+            let _ = writeln!(text, "{} {}", name, self.get(name));
+        }
+        text
+    }
+}
+
+/// The screen where the settings can be changed.
+pub struct SettingsScreen {
+    pub settings: Settings,
+    /// Index in `NAMES`.
+    selected: usize,
+    pub changed: bool,
+}
+
+impl SettingsScreen {
+    pub fn new(settings: Settings) -> SettingsScreen {
+        SettingsScreen {
+            settings,
+            selected: 0,
+            changed: false,
+        }
+    }
+
+    /// Handles a key press, and tells whether the screen is still open.
+    pub fn press(&mut self, key: Keycode) -> bool {
+        let steps = match key {
+            Keycode::Escape | SETTINGS_KEY => return false,
+            Keycode::Up => {
+                self.selected = (self.selected + NAMES.len() - 1) % NAMES.len();
+                return true;
+            }
+            Keycode::Down => {
+                self.selected = (self.selected + 1) % NAMES.len();
+                return true;
+            }
+            Keycode::Left => -1,
+            Keycode::Right => 1,
+            _ => return true,
+        };
+        let before = self.settings;
+        self.settings.adjust(NAMES[self.selected], steps);
+        self.changed |= self.settings != before;
+        true
+    }
+
+    /// The lines to show, with whether each one is selected.
+    pub fn lines(&self) -> Vec<(String, bool)> {
+        let mut lines = vec![
+            ("Settings".to_string(), false),
+            ("Up/Down select, Left/Right change,".to_string(), false),
+            ("Esc back".to_string(), false),
+        ];
+        for (index, name) in NAMES.iter().enumerate() {
+            let line = format!("{}: {}", name, self.settings.get(name));
+            lines.push((line, index == self.selected));
+        }
+        lines.push(("Rules and level apply to new games,".to_string(), false));
+        lines.push(("the window size next time".to_string(), false));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_level_fits_the_mode_wherever_it_comes() {
+        let (settings, errors) = Settings::parse("start_level 0\nmode Classic\n");
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(
+            (settings.mode, settings.start_level),
+            (GameModeKind::Classic, 0)
+        );
+
+        let (settings, errors) = Settings::parse("start_level 0\nmode Sprint\n");
+        assert_eq!(
+            errors,
+            vec!["line 1: start_level: Sprint starts at levels 1 to 20".to_string()]
+        );
+        assert_eq!(settings.start_level, 1);
+
+        let (settings, errors) = Settings::parse("mode Classic\nstart_level 20\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(settings.start_level, 19);
+    }
+
+    #[test]
+    fn settings_come_back_as_written() {
+        let settings = Settings {
+            mode: GameModeKind::Sprint,
+            start_level: 5,
+            das: 7,
+            ghost: false,
+            theme: Theme::Ocean,
+            ..Settings::default()
+        };
+        assert_eq!(Settings::parse(&settings.to_text()), (settings, Vec::new()));
+    }
+
+    #[test]
+    fn broken_settings_are_told_by_line() {
+        let text = "# comment\n\ndas 40\nspeed 3\narr 31\nghost maybe\ntheme Pink\nmode Tetris\n";
+        let (settings, errors) = Settings::parse(text);
+        assert_eq!(
+            errors,
+            [
+                "line 4: unknown setting 'speed'",
+                "line 5: arr: invalid value '31', expected 0 to 30",
+                "line 6: ghost: invalid value 'maybe', expected true or false",
+                "line 7: theme: unknown theme 'Pink'",
+                "line 8: mode: unknown mode 'Tetris'",
+            ]
+        );
+        // what could be read is kept, the rest stays as it was
+        let defaults = Settings::default();
+        assert_eq!(
+            settings,
+            Settings {
+                das: 40,
+                ..defaults
+            }
+        );
+
+        let (_, errors) = Settings::parse("previews\nwindow_width -5");
+        assert_eq!(
+            errors,
+            [
+                "line 1: previews: invalid value '', expected 1 to 5",
+                "line 2: window_width: invalid value '-5', expected 200 to 4000",
+            ]
+        );
+    }
+}